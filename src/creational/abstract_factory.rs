@@ -19,6 +19,8 @@ trait LightBulb {
     fn switch(&mut self, command: bool) -> Result<bool, Box<dyn Error>>;
 }
 
+// The full range a fan offers, the demo only switches between some of them.
+#[allow(dead_code)]
 #[derive(PartialEq, PartialOrd, Debug)]
 enum FanSpeed {
     Speed0 = 0,
@@ -41,6 +43,7 @@ trait DeviceFactory {
 
 #[derive(Debug)]
 struct PhilipsLightBulb {
+    #[allow(dead_code)]
     sensor_config: PhilipsSensor,
     state: bool,
 }
@@ -60,6 +63,7 @@ impl LightBulb for PhilipsLightBulb {
 }
 #[derive(Debug)]
 struct PhilipsFan {
+    #[allow(dead_code)]
     sensor_config: PhilipsSensor,
     state: FanSpeed,
 }
@@ -78,6 +82,8 @@ impl Fan for PhilipsFan {
     }
 }
 
+// Mirrors a real device handle, the demo only ever prints it.
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 struct PhilipsSensor {
     brand: String,
@@ -103,6 +109,7 @@ impl DeviceFactory for PhilipsSensor {
 
 #[derive(Debug)]
 struct SamsungLightBulb {
+    #[allow(dead_code)]
     sensor_config: SamsungSensor,
     state: bool,
 }
@@ -123,6 +130,7 @@ impl LightBulb for SamsungLightBulb {
 
 #[derive(Debug)]
 struct SamsungFan {
+    #[allow(dead_code)]
     sensor_config: SamsungSensor,
     state: FanSpeed,
 }
@@ -141,6 +149,8 @@ impl Fan for SamsungFan {
     }
 }
 
+// Mirrors a real device handle, the demo only ever prints it.
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 struct SamsungSensor {
     brand: String,
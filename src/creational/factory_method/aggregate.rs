@@ -0,0 +1,332 @@
+use super::{DocumentEditor, DocumentError, Location, Record, as_number, field_at};
+use serde::Serialize;
+use serde_json::{Number, Value};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashSet},
+    fmt,
+};
+
+/// One value computed over the records of a group. Fields can be dotted paths.
+///
+/// Sums, means, minimums and maximums only look at numbers, and at text that spells one,
+/// which is how numbers arrive from csv. Minimums and maximums of a field without any numbers
+/// compare its text instead. Null and missing fields are skipped by all but `Count`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Aggregate {
+    /// How many records the group has.
+    Count,
+    Sum(String),
+    Min(String),
+    Max(String),
+    Mean(String),
+    /// How many different values the field has. Each different value is kept in memory once.
+    Distinct(String),
+}
+
+impl Aggregate {
+    /// The column the aggregate gets in a report, like `mean(age)`.
+    pub fn label(&self) -> String {
+        match self {
+            Aggregate::Count => "count".to_string(),
+            Aggregate::Sum(field) => format!("sum({})", field),
+            Aggregate::Min(field) => format!("min({})", field),
+            Aggregate::Max(field) => format!("max({})", field),
+            Aggregate::Mean(field) => format!("mean({})", field),
+            Aggregate::Distinct(field) => format!("distinct({})", field),
+        }
+    }
+
+    fn start(&self) -> State {
+        match self {
+            Aggregate::Count => State::Count(0),
+            Aggregate::Sum(_) => State::Sum(0.0),
+            Aggregate::Min(_) => State::Extreme(Extreme::new(Ordering::Less)),
+            Aggregate::Max(_) => State::Extreme(Extreme::new(Ordering::Greater)),
+            Aggregate::Mean(_) => State::Mean {
+                total: 0.0,
+                count: 0,
+            },
+            Aggregate::Distinct(_) => State::Distinct(HashSet::new()),
+        }
+    }
+
+    fn field(&self) -> Option<&str> {
+        match self {
+            Aggregate::Count => None,
+            Aggregate::Sum(field)
+            | Aggregate::Min(field)
+            | Aggregate::Max(field)
+            | Aggregate::Mean(field)
+            | Aggregate::Distinct(field) => Some(field),
+        }
+    }
+}
+
+/// What records are grouped on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GroupBy {
+    Field(String),
+    /// The first `len` characters of the field's text.
+    Prefix {
+        field: String,
+        len: usize,
+    },
+}
+
+impl GroupBy {
+    pub fn label(&self) -> String {
+        match self {
+            GroupBy::Field(field) => field.clone(),
+            GroupBy::Prefix { field, len } => format!("prefix({}, {})", field, len),
+        }
+    }
+
+    fn key(&self, record: &Value) -> Value {
+        match self {
+            GroupBy::Field(field) => lookup(record, field).clone(),
+            GroupBy::Prefix { field, len } => match lookup(record, field) {
+                Value::Null => Value::Null,
+                value => Value::String(text(value).chars().take(*len).collect()),
+            },
+        }
+    }
+}
+
+/// Aggregates to compute over the records of a document in a single pass, optionally
+/// per group. Only the running totals are kept, so documents never have to fit in memory.
+#[derive(Debug, Clone, Default)]
+pub struct Aggregation {
+    aggregates: Vec<Aggregate>,
+    group_by: Option<GroupBy>,
+}
+
+impl Aggregation {
+    pub fn new() -> Self {
+        Aggregation::default()
+    }
+
+    /// Adds a column to the report.
+    pub fn aggregate(mut self, aggregate: Aggregate) -> Self {
+        self.aggregates.push(aggregate);
+        self
+    }
+
+    /// Computes the aggregates per value of `field` instead of over all records.
+    pub fn group_by(mut self, field: &str) -> Self {
+        self.group_by = Some(GroupBy::Field(field.to_string()));
+        self
+    }
+
+    /// Computes the aggregates per value of the first `len` characters of `field`.
+    pub fn group_by_prefix(mut self, field: &str, len: usize) -> Self {
+        self.group_by = Some(GroupBy::Prefix {
+            field: field.to_string(),
+            len,
+        });
+        self
+    }
+
+    /// Folds `records` into the report, stopping at the first record that cannot be read.
+    /// `name` stands in for the file name in errors.
+    pub fn run<T: Record>(
+        &self,
+        records: impl IntoIterator<Item = Result<T, DocumentError>>,
+        name: &str,
+    ) -> Result<AggregateReport, DocumentError> {
+        let mut groups: BTreeMap<String, (Option<Value>, Vec<State>)> = BTreeMap::new();
+        if self.group_by.is_none() {
+            // Without groups there is always one row, even for a document without records.
+            groups.insert(String::new(), (None, self.start()));
+        }
+        for record in records {
+            let record = serde_json::to_value(record?).map_err(|err| {
+                DocumentError::schema_mismatch(name, Location::default(), err.to_string())
+            })?;
+            let key = self.group_by.as_ref().map(|group_by| group_by.key(&record));
+            let (_, states) = groups
+                .entry(key.as_ref().map_or_else(String::new, text))
+                .or_insert_with(|| (key, self.start()));
+            for (aggregate, state) in self.aggregates.iter().zip(states) {
+                let value = aggregate
+                    .field()
+                    .map_or(&Value::Null, |field| lookup(&record, field));
+                state.add(value);
+            }
+        }
+
+        let mut columns: Vec<String> = self.group_by.iter().map(GroupBy::label).collect();
+        columns.extend(self.aggregates.iter().map(Aggregate::label));
+        let groups = groups
+            .into_values()
+            .map(|(key, states)| GroupResult {
+                key,
+                values: states.into_iter().map(State::finish).collect(),
+            })
+            .collect();
+        Ok(AggregateReport { columns, groups })
+    }
+
+    fn start(&self) -> Vec<State> {
+        self.aggregates.iter().map(Aggregate::start).collect()
+    }
+}
+
+impl<T: Record> DocumentEditor<T> {
+    /// Computes `aggregation` over the records of the document, read one at a time.
+    pub fn aggregate(&self, aggregation: &Aggregation) -> Result<AggregateReport, DocumentError> {
+        aggregation.run(self.read_records()?, &self.file_name)
+    }
+}
+
+/// One row of a report. `key` is the group's value, or `None` when nothing is grouped.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GroupResult {
+    pub key: Option<Value>,
+    /// One value per aggregate, in the order they were added. Null when the group has
+    /// no value to compute it from, e.g. the mean of a field that is always empty.
+    pub values: Vec<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AggregateReport {
+    /// The group column, when there is one, followed by one column per aggregate.
+    pub columns: Vec<String>,
+    /// The groups in the order of their keys.
+    pub groups: Vec<GroupResult>,
+}
+
+/// Prints the report as a table with aligned columns.
+impl fmt::Display for AggregateReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rows: Vec<Vec<String>> = self
+            .groups
+            .iter()
+            .map(|group| group.key.iter().chain(&group.values).map(text).collect())
+            .collect();
+        let widths: Vec<usize> = (0..self.columns.len())
+            .map(|column| {
+                rows.iter()
+                    .map(|row| row[column].chars().count())
+                    .chain([self.columns[column].chars().count()])
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+        let mut lines = vec![self.columns.clone()];
+        lines.extend(rows);
+        for (i, line) in lines.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            let cells: Vec<String> = line
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect();
+            write!(f, "{}", cells.join("  ").trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+enum State {
+    Count(u64),
+    Sum(f64),
+    Mean { total: f64, count: u64 },
+    Extreme(Extreme),
+    Distinct(HashSet<String>),
+}
+
+impl State {
+    fn add(&mut self, value: &Value) {
+        match self {
+            State::Count(count) => *count += 1,
+            _ if value.is_null() => {}
+            State::Sum(total) => *total += as_number(value).unwrap_or_default(),
+            State::Mean { total, count } => {
+                if let Some(number) = as_number(value) {
+                    *total += number;
+                    *count += 1;
+                }
+            }
+            State::Extreme(extreme) => extreme.add(value),
+            State::Distinct(seen) => {
+                seen.insert(text(value));
+            }
+        }
+    }
+
+    fn finish(self) -> Value {
+        match self {
+            State::Count(count) => Value::from(count),
+            State::Sum(total) => number_value(total),
+            State::Mean { count: 0, .. } => Value::Null,
+            State::Mean { total, count } => number_value(total / count as f64),
+            State::Extreme(extreme) => match (extreme.number, extreme.text) {
+                (Some(number), _) => number_value(number),
+                (None, Some(text)) => Value::String(text),
+                (None, None) => Value::Null,
+            },
+            State::Distinct(seen) => Value::from(seen.len()),
+        }
+    }
+}
+
+/// The smallest or largest value seen, going by `keep`.
+struct Extreme {
+    keep: Ordering,
+    number: Option<f64>,
+    text: Option<String>,
+}
+
+impl Extreme {
+    fn new(keep: Ordering) -> Self {
+        Extreme {
+            keep,
+            number: None,
+            text: None,
+        }
+    }
+
+    fn add(&mut self, value: &Value) {
+        if let Some(number) = as_number(value) {
+            if self
+                .number
+                .is_none_or(|best| number.partial_cmp(&best) == Some(self.keep))
+            {
+                self.number = Some(number);
+            }
+        } else {
+            let text = text(value);
+            if self
+                .text
+                .as_ref()
+                .is_none_or(|best| text.cmp(best) == self.keep)
+            {
+                self.text = Some(text);
+            }
+        }
+    }
+}
+
+fn lookup<'a>(record: &'a Value, field: &str) -> &'a Value {
+    field_at(record, field).unwrap_or(&Value::Null)
+}
+
+/// A whole number as an integer, so a sum of ages prints as `71` rather than `71.0`.
+fn number_value(number: f64) -> Value {
+    const EXACT: f64 = (1u64 << 53) as f64;
+    if number.fract() == 0.0 && number.abs() < EXACT {
+        Value::from(number as i64)
+    } else {
+        Number::from_f64(number).map_or(Value::Null, Value::Number)
+    }
+}
+
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
@@ -0,0 +1,297 @@
+use super::{
+    CsvProcessor, DocumentError, DocumentProcessor, Encoding, JsonProcessor, Record,
+    RecordValidator, check_record, decode, save_format, temp_file_name,
+};
+use std::{
+    future::Future,
+    io::{self, Read, Write},
+    panic,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    fs,
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    task,
+};
+
+/// A boxed future, so `AsyncDocumentProcessor` can still be used as a trait object.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// The async counterpart of `DocumentProcessor`, for services running on tokio.
+/// The I/O is async: a document is read into memory, or built up there, without
+/// blocking the executor. Parsing and serializing it is left to the blocking processor
+/// on tokio's blocking threads, so a large document does not hold up other tasks either.
+/// The processor and the records are shared with that thread, hence the `Arc`s.
+/// Unlike the blocking API this holds the whole document in memory.
+pub trait AsyncDocumentProcessor<T: Record + Send + Sync>: Send + Sync {
+    /// Reads every record from `source`, stopping at the first one that fails.
+    /// `name` stands in for the file name in errors.
+    fn read_async<'a>(
+        self: Arc<Self>,
+        source: &'a mut (dyn AsyncRead + Send + Unpin),
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<T>, DocumentError>>;
+
+    /// Writes `records` to `sink` and flushes it.
+    fn write_async<'a>(
+        self: Arc<Self>,
+        sink: &'a mut (dyn AsyncWrite + Send + Unpin),
+        name: &'a str,
+        records: Arc<[T]>,
+    ) -> BoxFuture<'a, Result<(), DocumentError>>;
+}
+
+impl<T: Record + Send + Sync> AsyncDocumentProcessor<T> for CsvProcessor {
+    fn read_async<'a>(
+        self: Arc<Self>,
+        source: &'a mut (dyn AsyncRead + Send + Unpin),
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<T>, DocumentError>> {
+        Box::pin(read_buffered(self, source, name))
+    }
+
+    fn write_async<'a>(
+        self: Arc<Self>,
+        sink: &'a mut (dyn AsyncWrite + Send + Unpin),
+        name: &'a str,
+        records: Arc<[T]>,
+    ) -> BoxFuture<'a, Result<(), DocumentError>> {
+        Box::pin(write_buffered(self, sink, name, records))
+    }
+}
+
+impl<T: Record + Send + Sync> AsyncDocumentProcessor<T> for JsonProcessor {
+    fn read_async<'a>(
+        self: Arc<Self>,
+        source: &'a mut (dyn AsyncRead + Send + Unpin),
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<T>, DocumentError>> {
+        Box::pin(read_buffered(self, source, name))
+    }
+
+    fn write_async<'a>(
+        self: Arc<Self>,
+        sink: &'a mut (dyn AsyncWrite + Send + Unpin),
+        name: &'a str,
+        records: Arc<[T]>,
+    ) -> BoxFuture<'a, Result<(), DocumentError>> {
+        Box::pin(write_buffered(self, sink, name, records))
+    }
+}
+
+async fn read_buffered<T, P>(
+    processor: Arc<P>,
+    source: &mut (dyn AsyncRead + Send + Unpin),
+    name: &str,
+) -> Result<Vec<T>, DocumentError>
+where
+    T: Record + Send,
+    P: DocumentProcessor<T> + Send + Sync + 'static,
+{
+    let mut document = Vec::new();
+    source
+        .read_to_end(&mut document)
+        .await
+        .map_err(|err| DocumentError::io(name, err))?;
+    let owned_name = name.to_string();
+    blocking(name, move || {
+        processor
+            .read_from(Box::new(document.as_slice()), &owned_name)?
+            .collect()
+    })
+    .await
+}
+
+async fn write_buffered<T, P>(
+    processor: Arc<P>,
+    sink: &mut (dyn AsyncWrite + Send + Unpin),
+    name: &str,
+    records: Arc<[T]>,
+) -> Result<(), DocumentError>
+where
+    T: Record + Send + Sync,
+    P: DocumentProcessor<T> + Send + Sync + 'static,
+{
+    let owned_name = name.to_string();
+    let document = blocking(name, move || {
+        let buffer = SharedBuffer::default();
+        let mut writer = processor.writer_to(Box::new(buffer.clone()), &owned_name)?;
+        for record in records.iter() {
+            writer.write_record(record)?;
+        }
+        writer.finish()?;
+        Ok(buffer.take())
+    })
+    .await?;
+    let io_error = |err| DocumentError::io(name, err);
+    sink.write_all(&document).await.map_err(io_error)?;
+    sink.flush().await.map_err(io_error)
+}
+
+/// Runs `work` on tokio's blocking threads. A panic there is passed on to the caller.
+async fn blocking<R, F>(name: &str, work: F) -> Result<R, DocumentError>
+where
+    R: Send + 'static,
+    F: FnOnce() -> Result<R, DocumentError> + Send + 'static,
+{
+    match task::spawn_blocking(work).await {
+        Ok(result) => result,
+        Err(err) if err.is_panic() => panic::resume_unwind(err.into_panic()),
+        // Only happens while the runtime shuts down.
+        Err(err) => Err(DocumentError::io(name, io::Error::other(err))),
+    }
+}
+
+/// An in-memory sink the blocking writers can own while the bytes stay reachable.
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.lock().unwrap_or_else(|err| err.into_inner()))
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Like `DocumentEditor`, with every method async. Files are decompressed and decoded
+/// the same way, and saved compressed and encoded like they are.
+pub struct AsyncDocumentEditor<T: Record + Send + Sync> {
+    file_name: String,
+    processor: Arc<dyn AsyncDocumentProcessor<T>>,
+    validator: Option<Arc<dyn RecordValidator<T> + Send + Sync>>,
+    /// Read and write in this encoding instead of detecting it.
+    encoding: Option<Encoding>,
+    keep_encoding: bool,
+}
+
+impl<T: Record + Send + Sync> AsyncDocumentEditor<T> {
+    pub(super) fn new(file_name: String, processor: Arc<dyn AsyncDocumentProcessor<T>>) -> Self {
+        AsyncDocumentEditor {
+            file_name,
+            processor,
+            validator: None,
+            encoding: None,
+            keep_encoding: false,
+        }
+    }
+
+    /// Like `DocumentEditor::with_validator`.
+    pub fn with_validator(
+        mut self,
+        validator: impl RecordValidator<T> + Send + Sync + 'static,
+    ) -> Self {
+        self.validator = Some(Arc::new(validator));
+        self
+    }
+
+    /// Like `DocumentEditor::with_encoding`.
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = Some(encoding);
+        self
+    }
+
+    /// Like `DocumentEditor::keep_encoding`.
+    pub fn keep_encoding(mut self) -> Self {
+        self.keep_encoding = true;
+        self
+    }
+
+    pub async fn read_records(&self) -> Result<Vec<T>, DocumentError> {
+        let io_error = |err| DocumentError::io(&self.file_name, err);
+        let file = fs::read(&self.file_name).await.map_err(io_error)?;
+        let (file_name, encoding) = (self.file_name.clone(), self.encoding);
+        let document = blocking(&self.file_name, move || {
+            let mut document = Vec::new();
+            decode(Box::new(file.as_slice()), encoding)
+                .and_then(|mut source| source.read_to_end(&mut document))
+                .map_err(|err| DocumentError::io(&file_name, err))?;
+            Ok(document)
+        })
+        .await?;
+        let records = self
+            .processor
+            .clone()
+            .read_async(&mut document.as_slice(), &self.file_name)
+            .await?;
+        let Some(validator) = self.validator.clone() else {
+            return Ok(records);
+        };
+        let file_name = self.file_name.clone();
+        blocking(&self.file_name, move || {
+            records
+                .into_iter()
+                .enumerate()
+                .map(|(index, record)| check_record(Some(&*validator), &file_name, index, record))
+                .collect()
+        })
+        .await
+    }
+
+    /// The first record. The whole document is still read and parsed.
+    pub async fn read_data(&self) -> Result<T, DocumentError> {
+        match self.read_records().await?.into_iter().next() {
+            Some(record) => Ok(record),
+            None => Err(DocumentError::EmptyDocument {
+                path: self.file_name.clone(),
+            }),
+        }
+    }
+
+    /// Replaces the document with `records`, through a temp file like `DocumentEditor::save`.
+    /// A slice of records that can be cloned converts as well as a `Vec`.
+    pub async fn save(&self, records: impl Into<Arc<[T]>>) -> Result<(), DocumentError> {
+        let temp_name = temp_file_name(&self.file_name);
+        let result = self.write_temp(&temp_name, records.into()).await;
+        match result {
+            Ok(()) => fs::rename(&temp_name, &self.file_name)
+                .await
+                .map_err(|err| DocumentError::io(&self.file_name, err)),
+            Err(err) => {
+                let _ = fs::remove_file(&temp_name).await;
+                Err(err.with_path(&self.file_name))
+            }
+        }
+    }
+
+    async fn write_temp(&self, temp_name: &str, records: Arc<[T]>) -> Result<(), DocumentError> {
+        let mut document = Vec::new();
+        self.processor
+            .clone()
+            .write_async(&mut document, temp_name, records)
+            .await?;
+        let (file_name, encoding, keep_encoding) =
+            (self.file_name.clone(), self.encoding, self.keep_encoding);
+        let document = blocking(temp_name, move || {
+            let (compression, encoding) = save_format(&file_name, encoding, keep_encoding)?;
+            let buffer = SharedBuffer::default();
+            compression
+                .encode(Box::new(buffer.clone()))
+                .map(|sink| encoding.encoder(sink))
+                .and_then(|mut sink| {
+                    sink.write_all(&document)?;
+                    sink.flush()
+                })
+                .map_err(|err| DocumentError::io(&file_name, err))?;
+            Ok(buffer.take())
+        })
+        .await?;
+        let io_error = |err| DocumentError::io(temp_name, err);
+        let mut file = fs::File::create(temp_name).await.map_err(io_error)?;
+        file.write_all(&document).await.map_err(io_error)?;
+        file.sync_all().await.map_err(io_error)
+    }
+}
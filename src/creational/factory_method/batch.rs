@@ -0,0 +1,158 @@
+use super::{DocumentEditorFactory, DocumentError, Location, ProcessorRegistry, Record};
+use std::{
+    fmt, fs,
+    path::Path,
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The outcome of reading one file of a batch.
+#[derive(Debug)]
+pub struct FileResult<T> {
+    pub path: String,
+    /// Every record of the file, or the first error that stopped reading it.
+    pub result: Result<Vec<T>, DocumentError>,
+    pub elapsed: Duration,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BatchSummary {
+    pub succeeded: usize,
+    pub failed: usize,
+    pub records: usize,
+    /// Wall clock time for the whole batch.
+    pub elapsed: Duration,
+    /// Time spent on the files themselves, added up over all workers.
+    pub busy: Duration,
+    pub slowest: Option<(String, Duration)>,
+}
+
+impl fmt::Display for BatchSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} files read, {} failed, {} records in {:.2?} ({:.2?} of work)",
+            self.succeeded, self.failed, self.records, self.elapsed, self.busy
+        )?;
+        if let Some((path, elapsed)) = &self.slowest {
+            write!(f, ", slowest {} at {:.2?}", path, elapsed)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct BatchReport<T> {
+    /// One result per file, in the order the files were found.
+    pub files: Vec<FileResult<T>>,
+    pub summary: BatchSummary,
+}
+
+/// Reads every document matching `pattern` on up to `threads` worker threads.
+/// `pattern` is a directory, whose files are all read, or a glob like `exports/*.csv*`.
+/// Each file gets an editor from `DocumentEditorFactory::create_editor_auto_with`, so the
+/// format comes from the extension or the content. A file that fails is reported in the
+/// result and does not stop the others.
+pub fn process_batch<T: Record + Send>(
+    registry: &ProcessorRegistry<T>,
+    pattern: &str,
+    threads: usize,
+) -> Result<BatchReport<T>, DocumentError> {
+    let started = Instant::now();
+    let paths = batch_paths(pattern)?;
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<FileResult<T>>>> =
+        Mutex::new(paths.iter().map(|_| None).collect());
+
+    thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, paths.len().max(1)) {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(path) = paths.get(index) else {
+                        return;
+                    };
+                    let file_started = Instant::now();
+                    let result =
+                        DocumentEditorFactory::create_editor_auto_with(registry, path.clone())
+                            .and_then(|editor| editor.read_records()?.collect());
+                    let file_result = FileResult {
+                        path: path.clone(),
+                        result,
+                        elapsed: file_started.elapsed(),
+                    };
+                    results.lock().unwrap_or_else(|err| err.into_inner())[index] =
+                        Some(file_result);
+                }
+            });
+        }
+    });
+
+    let files: Vec<FileResult<T>> = results
+        .into_inner()
+        .unwrap_or_else(|err| err.into_inner())
+        .into_iter()
+        .flatten()
+        .collect();
+    let mut summary = BatchSummary {
+        elapsed: started.elapsed(),
+        ..BatchSummary::default()
+    };
+    for file in &files {
+        match &file.result {
+            Ok(records) => {
+                summary.succeeded += 1;
+                summary.records += records.len();
+            }
+            Err(_) => summary.failed += 1,
+        }
+        summary.busy += file.elapsed;
+        if summary
+            .slowest
+            .as_ref()
+            .is_none_or(|(_, slowest)| file.elapsed > *slowest)
+        {
+            summary.slowest = Some((file.path.clone(), file.elapsed));
+        }
+    }
+    Ok(BatchReport { files, summary })
+}
+
+/// The files a batch pattern stands for, sorted. Hidden files are left out of a
+/// directory, which also skips the temp files of saves that are still running.
+fn batch_paths(pattern: &str) -> Result<Vec<String>, DocumentError> {
+    let mut paths = Vec::new();
+    if Path::new(pattern).is_dir() {
+        let entries = fs::read_dir(pattern).map_err(|err| DocumentError::io(pattern, err))?;
+        for entry in entries {
+            let entry = entry.map_err(|err| DocumentError::io(pattern, err))?;
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if !hidden && entry.path().is_file() {
+                paths.push(entry.path().to_string_lossy().into_owned());
+            }
+        }
+    } else {
+        let matches = glob::glob(pattern).map_err(|err| {
+            let location = Location {
+                column: Some(err.pos + 1),
+                ..Location::default()
+            };
+            DocumentError::parse(pattern, location, format!("invalid glob, {}", err.msg))
+        })?;
+        for found in matches {
+            let found = found.map_err(|err| {
+                let path = err.path().to_string_lossy().into_owned();
+                DocumentError::io(&path, err.into())
+            })?;
+            if found.is_file() {
+                paths.push(found.to_string_lossy().into_owned());
+            }
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
@@ -0,0 +1,377 @@
+use super::{DocumentEditor, DocumentError, Record};
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    fmt,
+    fs::{self, File},
+    hash::{DefaultHasher, Hasher},
+    io::{self, Read},
+    sync::Arc,
+    time::SystemTime,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Cached documents found to have changed on disk.
+    pub invalidations: u64,
+    /// Cached documents dropped to stay within the memory budget.
+    pub evictions: u64,
+}
+
+impl CacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            reads => self.hits as f64 / reads as f64,
+        }
+    }
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} hits, {} misses ({:.0}% hit rate), {} invalidated, {} evicted",
+            self.hits,
+            self.misses,
+            self.hit_rate() * 100.0,
+            self.invalidations,
+            self.evictions
+        )
+    }
+}
+
+/// What a cached document is compared on to tell whether the file changed.
+#[derive(Debug, PartialEq)]
+struct Fingerprint {
+    modified: Option<SystemTime>,
+    size: u64,
+    hash: Option<u64>,
+}
+
+struct Entry<T> {
+    records: Arc<[T]>,
+    fingerprint: Fingerprint,
+    /// What the entry counts against the budget.
+    cost: u64,
+    last_used: u64,
+}
+
+/// A path and how it is read. Editors reading the same file with another processor,
+/// dialect, encoding or validator get other records out of it, and an entry of their own.
+type Key = (String, String);
+
+/// Parsed records of documents that are read again and again, keyed by path. Any editor
+/// that reads the file the same way gets the cached records, also a new one made for
+/// every request.
+///
+/// A cached document is parsed again when its modification time or size changed, or,
+/// with `with_content_hash`, its content. Hashing reads the whole file on every lookup,
+/// which is still cheaper than parsing it, and catches edits that keep the size within
+/// the resolution of the file system's clock.
+///
+/// The memory budget counts the bytes of the cached documents as the processors read them,
+/// decompressed and in UTF-8, a lower bound for the memory their records take. The least
+/// recently used documents are dropped to stay within it, and a document larger than the
+/// whole budget is never cached.
+pub struct DocumentCache<T: Record> {
+    entries: HashMap<Key, Entry<T>>,
+    budget: u64,
+    used: u64,
+    content_hash: bool,
+    clock: u64,
+    stats: CacheStats,
+}
+
+impl<T: Record> DocumentCache<T> {
+    pub fn new(budget: u64) -> Self {
+        DocumentCache {
+            entries: HashMap::new(),
+            budget,
+            used: 0,
+            content_hash: false,
+            clock: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Also compares a hash of the file's content before handing out cached records.
+    pub fn with_content_hash(mut self) -> Self {
+        self.content_hash = true;
+        self
+    }
+
+    /// Every record of the editor's document, parsed only when the cache has no
+    /// up to date copy. The records are shared with the cache, not copied.
+    pub fn read_records(&mut self, editor: &DocumentEditor<T>) -> Result<Arc<[T]>, DocumentError> {
+        let path = editor.file_name.as_str();
+        // Taken before parsing, so a change made while parsing shows on the next read.
+        let fingerprint = self
+            .fingerprint(path)
+            .map_err(|err| DocumentError::io(path, err))?;
+        self.clock += 1;
+        let key = (path.to_string(), editor.cache_key());
+        if let Some(entry) = self.entries.get_mut(&key) {
+            if entry.fingerprint == fingerprint {
+                entry.last_used = self.clock;
+                self.stats.hits += 1;
+                return Ok(entry.records.clone());
+            }
+            self.stats.invalidations += 1;
+            self.remove(&key);
+        }
+
+        self.stats.misses += 1;
+        let read = Cell::new(0);
+        let source = Counted {
+            source: editor.open()?,
+            read: &read,
+        };
+        let records: Arc<[T]> = editor
+            .records_from(Box::new(source))?
+            .collect::<Result<Vec<T>, _>>()?
+            .into();
+        let cost = read.get();
+        if cost <= self.budget {
+            self.used += cost;
+            self.entries.insert(
+                key,
+                Entry {
+                    records: records.clone(),
+                    fingerprint,
+                    cost,
+                    last_used: self.clock,
+                },
+            );
+            self.evict();
+        }
+        Ok(records)
+    }
+
+    /// Like `DocumentEditor::read_data`, the first record of the document, from the cache.
+    pub fn read_data(&mut self, editor: &DocumentEditor<T>) -> Result<T, DocumentError>
+    where
+        T: Clone,
+    {
+        match self.read_records(editor)?.first() {
+            Some(record) => Ok(record.clone()),
+            None => Err(DocumentError::EmptyDocument {
+                path: editor.file_name.clone(),
+            }),
+        }
+    }
+
+    /// Drops the cached copies of `path`, e.g. after writing the document.
+    pub fn invalidate(&mut self, path: &str) {
+        let keys: Vec<Key> = self
+            .entries
+            .keys()
+            .filter(|(cached, _)| cached == path)
+            .cloned()
+            .collect();
+        for key in keys {
+            self.remove(&key);
+            self.stats.invalidations += 1;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.used = 0;
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// How many documents are cached, and the bytes they count against the budget.
+    pub fn usage(&self) -> (usize, u64) {
+        (self.entries.len(), self.used)
+    }
+
+    fn fingerprint(&self, path: &str) -> io::Result<Fingerprint> {
+        let metadata = fs::metadata(path)?;
+        let hash = match self.content_hash {
+            true => Some(hash_file(path)?),
+            false => None,
+        };
+        Ok(Fingerprint {
+            modified: metadata.modified().ok(),
+            size: metadata.len(),
+            hash,
+        })
+    }
+
+    fn remove(&mut self, key: &Key) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.used -= entry.cost;
+        }
+    }
+
+    /// Drops the least recently used documents until the cache fits its budget.
+    fn evict(&mut self) {
+        while self.used > self.budget {
+            let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                return;
+            };
+            self.remove(&oldest);
+            self.stats.evictions += 1;
+        }
+    }
+}
+
+/// Counts the bytes read through it.
+struct Counted<'a> {
+    source: Box<dyn Read>,
+    read: &'a Cell<u64>,
+}
+
+impl Read for Counted<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.source.read(buf)?;
+        self.read.set(self.read.get() + len as u64);
+        Ok(len)
+    }
+}
+
+fn hash_file(path: &str) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    let mut hasher = DefaultHasher::new();
+    let mut chunk = [0; 64 * 1024];
+    loop {
+        match file.read(&mut chunk)? {
+            0 => return Ok(hasher.finish()),
+            len => hasher.write(&chunk[..len]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creational::factory_method::{
+        DocumentEditorFactory, DocumentType, DynamicRecord, Encoding, test_files::TempDir,
+    };
+    use std::{io::Write, time::Duration};
+
+    fn csv_editor(path: &str) -> DocumentEditor<DynamicRecord> {
+        DocumentEditorFactory::create_editor(path.to_string(), DocumentType::Csv)
+    }
+
+    /// Rewrites the file and sets its modification time back, as an edit within the
+    /// resolution of the file system's clock would leave it.
+    fn rewrite_keeping_time(path: &str, contents: &str) {
+        let modified = fs::metadata(path).unwrap().modified().unwrap();
+        fs::write(path, contents).unwrap();
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+
+    #[test]
+    fn editors_that_read_the_same_way_share_entries() {
+        let dir = TempDir::new();
+        let path = dir.write("people.csv", "name;age\nann;30\n");
+        let mut cache = DocumentCache::new(1 << 20);
+        for _ in 0..3 {
+            let records = cache.read_records(&csv_editor(&path)).unwrap();
+            assert_eq!(records.len(), 1);
+        }
+        assert_eq!((cache.stats().hits, cache.stats().misses), (2, 1));
+
+        let latin1 = csv_editor(&path).with_encoding(Encoding::Latin1);
+        cache.read_records(&latin1).unwrap();
+        assert_eq!(cache.usage().0, 2);
+        assert_eq!(cache.stats().misses, 2);
+    }
+
+    #[test]
+    fn changed_files_are_parsed_again() {
+        let dir = TempDir::new();
+        let path = dir.write("people.csv", "name,age\nann,30\n");
+        let editor = csv_editor(&path);
+        let mut cache = DocumentCache::new(1 << 20);
+        cache.read_records(&editor).unwrap();
+
+        // Another size.
+        fs::write(&path, "name,age\nann,30\nbob,40\n").unwrap();
+        assert_eq!(cache.read_records(&editor).unwrap().len(), 2);
+
+        // The same size, another time.
+        fs::write(&path, "name,age\nann,31\nbob,40\n").unwrap();
+        let later = SystemTime::now() + Duration::from_secs(10);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        let records = cache.read_records(&editor).unwrap();
+        assert_eq!(records[0].get("age"), Some(&"31".into()));
+        assert_eq!(cache.stats().invalidations, 2);
+
+        // The same size and time is only caught by the hash.
+        rewrite_keeping_time(&path, "name,age\nann,32\nbob,40\n");
+        let records = cache.read_records(&editor).unwrap();
+        assert_eq!(records[0].get("age"), Some(&"31".into()));
+
+        let mut hashing = DocumentCache::new(1 << 20).with_content_hash();
+        hashing.read_records(&editor).unwrap();
+        rewrite_keeping_time(&path, "name,age\nann,33\nbob,40\n");
+        let records = hashing.read_records(&editor).unwrap();
+        assert_eq!(records[0].get("age"), Some(&"33".into()));
+        assert_eq!(hashing.stats().invalidations, 1);
+
+        hashing.invalidate(&path);
+        assert_eq!(hashing.usage(), (0, 0));
+    }
+
+    #[test]
+    fn the_least_recently_used_documents_are_evicted() {
+        let dir = TempDir::new();
+        // 14 bytes each, so two of them fit the budget.
+        let paths: Vec<String> = ["a", "b", "c"]
+            .iter()
+            .map(|name| dir.write(&format!("{}.csv", name), format!("name,age\n{},30\n", name)))
+            .collect();
+        let mut cache = DocumentCache::new(40);
+        cache.read_records(&csv_editor(&paths[0])).unwrap();
+        cache.read_records(&csv_editor(&paths[1])).unwrap();
+        cache.read_records(&csv_editor(&paths[0])).unwrap();
+        cache.read_records(&csv_editor(&paths[2])).unwrap();
+        assert_eq!(cache.usage(), (2, 28));
+        assert_eq!(cache.stats().evictions, 1);
+
+        cache.read_records(&csv_editor(&paths[0])).unwrap();
+        cache.read_records(&csv_editor(&paths[1])).unwrap();
+        assert_eq!((cache.stats().hits, cache.stats().misses), (2, 4));
+
+        let big = dir.write("big.csv", format!("name\n{}\n", "x".repeat(100)));
+        cache.read_records(&csv_editor(&big)).unwrap();
+        assert_eq!(cache.usage().0, 2);
+    }
+
+    #[test]
+    fn compressed_documents_count_their_decompressed_size() {
+        let dir = TempDir::new();
+        let text = format!("name,age\n{}", "ann,30\n".repeat(1000));
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(text.as_bytes()).unwrap();
+        let path = dir.write("people.csv.gz", encoder.finish().unwrap());
+        assert!(fs::metadata(&path).unwrap().len() < 100);
+
+        let mut cache = DocumentCache::new(1 << 20);
+        assert_eq!(cache.read_records(&csv_editor(&path)).unwrap().len(), 1000);
+        assert_eq!(cache.usage(), (1, text.len() as u64));
+    }
+}
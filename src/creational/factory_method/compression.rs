@@ -0,0 +1,221 @@
+use super::peek_head;
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    path::Path,
+};
+
+/// How a document file is compressed. The processors only ever see the decompressed
+/// bytes, `DocumentEditor` wraps the file in a decoder or encoder around them, so a
+/// compressed document is saved compressed the same way again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+/// `BZh` and a block size from `1` to `9` start every bzip2 stream, followed by the magic
+/// of its first block, or of the end of the stream when it is empty. Text can easily start
+/// with `BZh`, so all of it has to be there.
+const BZIP2_MAGIC: &[u8] = b"BZh";
+const BZIP2_BLOCK_MAGIC: &[u8] = &[0x31, 0x41, 0x59, 0x26, 0x53, 0x59];
+const BZIP2_END_MAGIC: &[u8] = &[0x17, 0x72, 0x45, 0x38, 0x50, 0x90];
+/// How many bytes it takes to recognize every compression.
+const MAGIC_LEN: usize = BZIP2_MAGIC.len() + 1 + BZIP2_BLOCK_MAGIC.len();
+
+impl Compression {
+    /// Maps a trailing `.gz`, `.zst`/`.zstd` or `.bz2` onto a compression, ignoring case.
+    pub fn from_extension(file_name: &str) -> Compression {
+        let extension = Path::new(file_name)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("gz") => Compression::Gzip,
+            Some("zst" | "zstd") => Compression::Zstd,
+            Some("bz2") => Compression::Bzip2,
+            _ => Compression::None,
+        }
+    }
+
+    /// Recognizes a compressed stream by its first bytes.
+    pub fn from_magic(head: &[u8]) -> Compression {
+        if head.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if head.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else if is_bzip2(head) {
+            Compression::Bzip2
+        } else {
+            Compression::None
+        }
+    }
+
+    /// The compression of an existing file going by its content, or, for a file that does not
+    /// exist yet, the one its extension asks for. Content wins, so a mislabeled file still reads.
+    pub fn detect(file_name: &str) -> io::Result<Compression> {
+        let mut head = Vec::with_capacity(MAGIC_LEN);
+        match File::open(file_name) {
+            Ok(file) => {
+                file.take(MAGIC_LEN as u64).read_to_end(&mut head)?;
+                Ok(Compression::from_magic(&head))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                Ok(Compression::from_extension(file_name))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// `file_name` without the extension that only says how it is compressed,
+    /// e.g. `data.csv` for `data.csv.gz`, so the format can be told from what is left.
+    pub fn strip_extension(file_name: &str) -> &str {
+        match Compression::from_extension(file_name) {
+            Compression::None => file_name,
+            _ => file_name
+                .rsplit_once('.')
+                .map_or(file_name, |(stem, _)| stem),
+        }
+    }
+
+    /// Decompresses `source` when its first bytes say it is compressed, and passes it
+    /// through untouched otherwise.
+    pub(super) fn decode<'a>(source: Box<dyn Read + 'a>) -> io::Result<Box<dyn Read + 'a>> {
+        let (head, source) = peek_head(source, MAGIC_LEN)?;
+        Ok(match Compression::from_magic(&head) {
+            Compression::None => source,
+            // The multi-member decoders also read files that were concatenated, as `cat a.gz b.gz` makes.
+            Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(source)),
+            Compression::Zstd => Box::new(zstd::Decoder::new(source)?),
+            Compression::Bzip2 => Box::new(bzip2::read::MultiBzDecoder::new(source)),
+        })
+    }
+
+    pub(super) fn encode(self, sink: Box<dyn Write>) -> io::Result<Box<dyn Write>> {
+        let encoder = match self {
+            Compression::None => return Ok(sink),
+            Compression::Gzip => Encoder::Gzip(flate2::write::GzEncoder::new(
+                sink,
+                flate2::Compression::default(),
+            )),
+            Compression::Zstd => Encoder::Zstd(zstd::Encoder::new(sink, 0)?),
+            Compression::Bzip2 => Encoder::Bzip2(bzip2::write::BzEncoder::new(
+                sink,
+                bzip2::Compression::default(),
+            )),
+        };
+        Ok(Box::new(CompressedSink {
+            encoder: Some(encoder),
+        }))
+    }
+}
+
+fn is_bzip2(head: &[u8]) -> bool {
+    let Some(rest) = head.strip_prefix(BZIP2_MAGIC) else {
+        return false;
+    };
+    match rest.split_first() {
+        Some((block_size, block)) => {
+            (b'1'..=b'9').contains(block_size)
+                && (block.starts_with(BZIP2_BLOCK_MAGIC) || block.starts_with(BZIP2_END_MAGIC))
+        }
+        None => false,
+    }
+}
+
+enum Encoder {
+    Gzip(flate2::write::GzEncoder<Box<dyn Write>>),
+    Zstd(zstd::Encoder<'static, Box<dyn Write>>),
+    Bzip2(bzip2::write::BzEncoder<Box<dyn Write>>),
+}
+
+/// Record writers end a document by flushing their sink, so flushing this one ends the
+/// compressed stream, writes its trailer, and flushes the sink below. Flushing again does nothing.
+struct CompressedSink {
+    encoder: Option<Encoder>,
+}
+
+impl Write for CompressedSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.encoder {
+            Some(Encoder::Gzip(encoder)) => encoder.write(buf),
+            Some(Encoder::Zstd(encoder)) => encoder.write(buf),
+            Some(Encoder::Bzip2(encoder)) => encoder.write(buf),
+            None => Err(io::Error::other(
+                "the compressed stream has already been finished",
+            )),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut sink = match self.encoder.take() {
+            Some(Encoder::Gzip(encoder)) => encoder.finish()?,
+            Some(Encoder::Zstd(encoder)) => encoder.finish()?,
+            Some(Encoder::Bzip2(encoder)) => encoder.finish()?,
+            None => return Ok(()),
+        };
+        sink.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// A sink whose bytes can still be looked at after the encoder owning it is dropped.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn compressed(compression: Compression, text: &[u8]) -> Vec<u8> {
+        let buffer = Shared::default();
+        let mut sink = compression.encode(Box::new(buffer.clone())).unwrap();
+        sink.write_all(text).unwrap();
+        sink.flush().unwrap();
+        drop(sink);
+        buffer.0.lock().unwrap().clone()
+    }
+
+    fn decoded(bytes: &[u8]) -> Vec<u8> {
+        let mut text = Vec::new();
+        Compression::decode(Box::new(bytes))
+            .unwrap()
+            .read_to_end(&mut text)
+            .unwrap();
+        text
+    }
+
+    #[test]
+    fn recognizes_every_compression_by_its_magic() {
+        for compression in [Compression::Gzip, Compression::Zstd, Compression::Bzip2] {
+            for text in [&b"name,age\nann,30\n"[..], b""] {
+                let bytes = compressed(compression, text);
+                assert_eq!(Compression::from_magic(&bytes), compression);
+                assert_eq!(decoded(&bytes), text);
+            }
+        }
+    }
+
+    #[test]
+    fn text_starting_like_bzip2_is_not_compressed() {
+        for text in [&b"BZhour,b\n1,2\n"[..], b"BZh9", b"BZh91AY&SX", b"BZh"] {
+            assert_eq!(Compression::from_magic(text), Compression::None);
+            assert_eq!(decoded(text), text);
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use super::{Compression, DocumentEditorFactory, DocumentError, DynamicRecord, ProcessorRegistry};
+use std::path::Path;
+
+/// A record that could not be carried over. `index` counts records from zero in the input.
+#[derive(Debug)]
+pub struct RecordFailure {
+    pub index: usize,
+    pub error: DocumentError,
+}
+
+#[derive(Debug, Default)]
+pub struct ConvertReport {
+    pub written: usize,
+    pub failures: Vec<RecordFailure>,
+}
+
+/// Reads `input` with one processor and writes every record to `output` with another.
+/// Formats are names from `registry`; a missing input format is detected like
+/// `create_editor_auto` does, a missing output format comes from the output extension.
+/// Records stream straight from the reader to the writer, and a record that fails to
+/// read or write is reported in the result instead of aborting the conversion.
+pub fn convert(
+    registry: &ProcessorRegistry<DynamicRecord>,
+    input: &str,
+    input_format: Option<&str>,
+    output: &str,
+    output_format: Option<&str>,
+) -> Result<ConvertReport, DocumentError> {
+    let reader = match input_format {
+        Some(format) => {
+            DocumentEditorFactory::create_editor_with(registry, input.to_string(), format)?
+        }
+        None => DocumentEditorFactory::create_editor_auto_with(registry, input.to_string())?,
+    };
+    let output_format = match output_format {
+        Some(format) => format.to_string(),
+        None => Path::new(Compression::strip_extension(output))
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(|extension| registry.format_for_extension(extension))
+            .map(str::to_string)
+            .ok_or_else(|| {
+                DocumentError::unsupported_format(
+                    output,
+                    "cannot tell the output format from the file name",
+                )
+            })?,
+    };
+    let writer =
+        DocumentEditorFactory::create_editor_with(registry, output.to_string(), &output_format)?;
+
+    let records = reader.read_records()?;
+    writer.save_with(|sink| {
+        let mut report = ConvertReport::default();
+        for (index, record) in records.enumerate() {
+            let written = record.and_then(|record| {
+                sink.write_record(&record)
+                    .map_err(|err| err.with_path(output))
+            });
+            match written {
+                Ok(()) => report.written += 1,
+                Err(error) => report.failures.push(RecordFailure { index, error }),
+            }
+        }
+        Ok(report)
+    })
+}
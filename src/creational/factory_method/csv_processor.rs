@@ -0,0 +1,656 @@
+use super::{
+    DocumentError, DocumentProcessor, Location, Record, RecordIter, RecordWriter, SNIFF_LEN,
+    peek_head, read_head, sample_lines, text_value::TextValue,
+};
+use csv::StringRecord;
+use serde::de::{
+    self,
+    value::{MapDeserializer, SeqDeserializer},
+};
+use serde_json::{Map, Value};
+use std::{
+    io::{Read, Write},
+    sync::Mutex,
+};
+
+/// Delimiters that are considered when a file's dialect is sniffed.
+pub(super) const DELIMITERS: [u8; 4] = [b',', b';', b'\t', b'|'];
+
+/// How a csv file is laid out. The default is plain RFC 4180 csv with a header row.
+/// A dialect can also be set up by hand, or detected from the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvDialect {
+    pub delimiter: u8,
+    pub quote: u8,
+    /// Escape character for quotes inside quoted fields. `None` means quotes are doubled (`""`).
+    pub escape: Option<u8>,
+    pub has_headers: bool,
+    /// Trims whitespace around fields and headers.
+    pub trim: bool,
+    /// Lines starting with this byte are skipped.
+    pub comment: Option<u8>,
+    /// Allows rows with a different number of fields than the first one.
+    pub flexible: bool,
+    /// Sniffs the dialect from the start of the file on every read, ignoring the fields above.
+    pub auto_detect: bool,
+}
+
+impl Default for CsvDialect {
+    fn default() -> Self {
+        CsvDialect {
+            delimiter: b',',
+            quote: b'"',
+            escape: None,
+            has_headers: true,
+            trim: false,
+            comment: None,
+            flexible: false,
+            auto_detect: false,
+        }
+    }
+}
+
+impl CsvDialect {
+    /// A dialect that is worked out from the file itself on every read.
+    pub fn auto() -> Self {
+        CsvDialect {
+            auto_detect: true,
+            ..CsvDialect::default()
+        }
+    }
+
+    /// Sniffs the dialect from the first few kilobytes of `file_name`.
+    pub fn detect(file_name: &str) -> Result<CsvDialect, DocumentError> {
+        let head = read_head(file_name).map_err(|err| DocumentError::io(file_name, err))?;
+        Ok(Self::detect_from(&head))
+    }
+
+    /// Sniffs the dialect from the start of a file. Anything the sample gives
+    /// no evidence for keeps its default value.
+    pub fn detect_from(head: &[u8]) -> CsvDialect {
+        let mut dialect = CsvDialect::default();
+        let mut lines = sample_lines(head, 20);
+        if lines.iter().any(|line| line.starts_with(b"#")) {
+            dialect.comment = Some(b'#');
+            lines.retain(|line| !line.starts_with(b"#"));
+        }
+        if lines.is_empty() {
+            return dialect;
+        }
+
+        let opens_field = |quote: u8| {
+            lines
+                .iter()
+                .filter(|line| {
+                    line.first() == Some(&quote)
+                        || line
+                            .windows(2)
+                            .any(|pair| DELIMITERS.contains(&pair[0]) && pair[1] == quote)
+                })
+                .count()
+        };
+        if opens_field(b'\'') > opens_field(b'"') {
+            dialect.quote = b'\'';
+        }
+        let backslash_quote = [b'\\', dialect.quote];
+        if lines
+            .iter()
+            .any(|line| line.windows(2).any(|pair| pair == backslash_quote))
+        {
+            dialect.escape = Some(b'\\');
+        }
+
+        // Prefer a delimiter that splits every line into the same number of fields,
+        // then the one that splits lines the most.
+        let quote = dialect.quote;
+        let best = DELIMITERS
+            .into_iter()
+            .map(|delimiter| {
+                let counts: Vec<usize> = lines
+                    .iter()
+                    .map(|line| count_unquoted(line, delimiter, quote))
+                    .collect();
+                let consistent = counts.iter().all(|&count| count == counts[0]);
+                let total: usize = counts.iter().sum();
+                (delimiter, consistent && counts[0] > 0, total, consistent)
+            })
+            .filter(|&(_, _, total, _)| total > 0)
+            .max_by_key(|&(_, fits, total, _)| (fits, total));
+        if let Some((delimiter, _, _, consistent)) = best {
+            dialect.delimiter = delimiter;
+            dialect.flexible = !consistent;
+        }
+
+        let rows: Vec<Vec<&[u8]>> = lines
+            .iter()
+            .map(|line| split_unquoted(line, dialect.delimiter, quote))
+            .collect();
+        // `a, b, c` style padding after every delimiter.
+        dialect.trim = rows.iter().all(|fields| {
+            fields.len() > 1
+                && fields[1..]
+                    .iter()
+                    .all(|field| field.first().is_some_and(u8::is_ascii_whitespace))
+        });
+        dialect.has_headers = looks_like_header(&rows);
+        dialect
+    }
+
+    fn reader_builder(&self) -> csv::ReaderBuilder {
+        let mut builder = csv::ReaderBuilder::new();
+        builder
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .escape(self.escape)
+            .double_quote(self.escape.is_none())
+            .has_headers(self.has_headers)
+            .trim(if self.trim {
+                csv::Trim::All
+            } else {
+                csv::Trim::None
+            })
+            .comment(self.comment)
+            .flexible(self.flexible);
+        builder
+    }
+
+    fn writer_builder(&self) -> csv::WriterBuilder {
+        let mut builder = csv::WriterBuilder::new();
+        builder
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .escape(self.escape.unwrap_or(b'\\'))
+            .double_quote(self.escape.is_none())
+            .has_headers(self.has_headers)
+            .flexible(self.flexible);
+        builder
+    }
+}
+
+/// A header row is assumed unless some column is numeric everywhere except in the first row.
+/// A first row with a number in a numeric column is taken as data.
+fn looks_like_header(rows: &[Vec<&[u8]>]) -> bool {
+    let is_number = |field: &&[u8]| {
+        let field = String::from_utf8_lossy(field);
+        field
+            .trim()
+            .trim_matches(['"', '\''])
+            .parse::<f64>()
+            .is_ok()
+    };
+    let Some((first, rest)) = rows.split_first() else {
+        return true;
+    };
+    if rest.is_empty() {
+        return true;
+    }
+    let mut numeric_first = false;
+    for (column, field) in first.iter().enumerate() {
+        let numeric_below = rest
+            .iter()
+            .all(|row| row.get(column).is_some_and(is_number));
+        if !numeric_below {
+            continue;
+        }
+        if !is_number(field) {
+            return true;
+        }
+        numeric_first = true;
+    }
+    !numeric_first
+}
+
+/// Splits a csv line on `delimiter`, leaving alone the delimiters inside `quote`d fields.
+pub(super) fn split_unquoted(line: &[u8], delimiter: u8, quote: u8) -> Vec<&[u8]> {
+    let mut fields = Vec::new();
+    let mut in_quotes = false;
+    let mut start = 0;
+    for (i, &byte) in line.iter().enumerate() {
+        if byte == quote {
+            in_quotes = !in_quotes;
+        } else if byte == delimiter && !in_quotes {
+            fields.push(&line[start..i]);
+            start = i + 1;
+        }
+    }
+    fields.push(&line[start..]);
+    fields
+}
+
+pub(super) fn count_unquoted(line: &[u8], delimiter: u8, quote: u8) -> usize {
+    split_unquoted(line, delimiter, quote).len() - 1
+}
+
+/// Builds a record from the text of a row rather than letting csv guess at the type of
+/// every cell, so a field the record leaves untyped keeps its text exactly as written,
+/// like the zip code `00501`. Without headers, fields are matched up by position.
+fn deserialize_row<T: Record>(
+    row: &StringRecord,
+    headers: Option<&StringRecord>,
+) -> Result<T, de::value::Error> {
+    match headers {
+        Some(headers) => {
+            T::deserialize(MapDeserializer::new(headers.iter().zip(row).map(
+                |(header, cell)| (header.to_string(), TextValue::new(header, cell)),
+            )))
+        }
+        None => T::deserialize(SeqDeserializer::new(
+            row.iter()
+                .enumerate()
+                .map(|(column, cell)| TextValue::new(column.to_string(), cell)),
+        )),
+    }
+}
+
+fn csv_location(position: Option<&csv::Position>) -> Location {
+    position.map_or_else(Location::default, |position| Location {
+        line: Some(position.line() as usize),
+        column: None,
+        offset: Some(position.byte()),
+    })
+}
+
+/// Sorts a csv error into the matching `DocumentError`, keeping the line and byte csv reports.
+fn csv_error(path: &str, err: csv::Error) -> DocumentError {
+    let message = err.to_string();
+    match err.into_kind() {
+        csv::ErrorKind::Io(source) => DocumentError::io(path, source),
+        csv::ErrorKind::Deserialize { pos, err } => {
+            DocumentError::schema_mismatch(path, csv_location(pos.as_ref()), err.to_string())
+        }
+        csv::ErrorKind::Serialize(message) => {
+            DocumentError::schema_mismatch(path, Location::default(), message)
+        }
+        csv::ErrorKind::UnequalLengths {
+            pos,
+            expected_len,
+            len,
+        } => DocumentError::parse(
+            path,
+            csv_location(pos.as_ref()),
+            format!(
+                "found a row with {} fields, but the previous row has {}",
+                len, expected_len
+            ),
+        ),
+        csv::ErrorKind::Utf8 { pos, err } => {
+            DocumentError::parse(path, csv_location(pos.as_ref()), err.to_string())
+        }
+        _ => DocumentError::parse(path, Location::default(), message),
+    }
+}
+
+/// One record per row, with the fields named by the header row, or by position without one.
+pub struct CsvProcessor {
+    pub dialect: CsvDialect,
+    /// The dialect found by the last auto-detecting read. Writes reuse it,
+    /// so saving a file keeps the dialect it was read with.
+    detected: Mutex<Option<CsvDialect>>,
+}
+
+impl CsvProcessor {
+    pub fn new(dialect: CsvDialect) -> Self {
+        CsvProcessor {
+            dialect,
+            detected: Mutex::new(None),
+        }
+    }
+
+    /// The dialect to read `source` with. Sniffing it consumes the start of the source,
+    /// so the source to read from afterwards is handed back.
+    fn read_dialect<'a>(
+        &self,
+        source: Box<dyn Read + 'a>,
+        name: &str,
+    ) -> Result<(CsvDialect, Box<dyn Read + 'a>), DocumentError> {
+        if !self.dialect.auto_detect {
+            return Ok((self.dialect.clone(), source));
+        }
+        let (head, source) =
+            peek_head(source, SNIFF_LEN).map_err(|err| DocumentError::io(name, err))?;
+        let detected = CsvDialect::detect_from(&head);
+        *self.detected.lock().unwrap_or_else(|err| err.into_inner()) = Some(detected.clone());
+        Ok((detected, source))
+    }
+
+    fn write_dialect(&self) -> CsvDialect {
+        let detected = self.detected.lock().unwrap_or_else(|err| err.into_inner());
+        match (&*detected, self.dialect.auto_detect) {
+            (Some(detected), true) => detected.clone(),
+            _ => self.dialect.clone(),
+        }
+    }
+
+    fn record_writer(&self, sink: Box<dyn Write>, name: &str, crlf: bool) -> CsvRecordWriter {
+        let dialect = self.write_dialect();
+        let mut builder = dialect.writer_builder();
+        if crlf {
+            builder.terminator(csv::Terminator::CRLF);
+        }
+        CsvRecordWriter {
+            wtr: builder.from_writer(sink),
+            file_name: name.to_string(),
+            has_headers: dialect.has_headers,
+            flexible: dialect.flexible,
+            columns: None,
+            width: None,
+        }
+    }
+}
+
+impl Default for CsvProcessor {
+    fn default() -> Self {
+        CsvProcessor::new(CsvDialect::default())
+    }
+}
+
+impl<T: Record> DocumentProcessor<T> for CsvProcessor {
+    fn cache_key(&self) -> String {
+        format!("CsvProcessor{:?}", self.dialect)
+    }
+
+    fn read_from<'a>(
+        &self,
+        source: Box<dyn Read + 'a>,
+        name: &str,
+    ) -> Result<RecordIter<'a, T>, DocumentError> {
+        let (dialect, source) = self.read_dialect(source, name)?;
+        let mut rdr = dialect.reader_builder().from_reader(source);
+        let headers = match dialect.has_headers {
+            true => Some(rdr.headers().map_err(|err| csv_error(name, err))?.clone()),
+            false => None,
+        };
+        let name = name.to_string();
+        Ok(Box::new(rdr.into_records().map(move |row| {
+            let row = row.map_err(|err| csv_error(&name, err))?;
+            deserialize_row(&row, headers.as_ref()).map_err(|err| {
+                DocumentError::schema_mismatch(&name, csv_location(row.position()), err.to_string())
+            })
+        })))
+    }
+
+    fn writer_to(
+        &self,
+        sink: Box<dyn Write>,
+        name: &str,
+    ) -> Result<Box<dyn RecordWriter<T>>, DocumentError> {
+        Ok(Box::new(self.record_writer(sink, name, false)))
+    }
+
+    /// Ends rows with `\r\n` when the document being replaced does.
+    fn writer_like(
+        &self,
+        sink: Box<dyn Write>,
+        name: &str,
+        original: &[u8],
+    ) -> Result<Box<dyn RecordWriter<T>>, DocumentError> {
+        let crlf = original
+            .split_inclusive(|&b| b == b'\n')
+            .next()
+            .is_some_and(|line| line.ends_with(b"\r\n"));
+        Ok(Box::new(self.record_writer(sink, name, crlf)))
+    }
+}
+
+/// Writes rows that all have the same columns. Every row is checked and put together in
+/// full before any of it is written, so a record that does not fit leaves nothing behind.
+struct CsvRecordWriter {
+    file_name: String,
+    wtr: csv::Writer<Box<dyn Write>>,
+    has_headers: bool,
+    flexible: bool,
+    /// The fields of the first record, in order. Later records are written in this order,
+    /// whatever order their own fields are in.
+    columns: Option<Vec<String>>,
+    /// How many fields the first record has, when it has no names for them.
+    width: Option<usize>,
+}
+
+impl CsvRecordWriter {
+    fn mismatch(&self, message: String) -> DocumentError {
+        DocumentError::schema_mismatch(&self.file_name, Location::default(), message)
+    }
+
+    fn cell(&self, field: &str, value: Value) -> Result<String, DocumentError> {
+        match value {
+            Value::Null => Ok(String::new()),
+            Value::String(text) => Ok(text),
+            Value::Bool(_) | Value::Number(_) => Ok(value.to_string()),
+            Value::Array(_) | Value::Object(_) => Err(self.mismatch(format!(
+                "field `{}` is nested, which csv cannot hold",
+                field
+            ))),
+        }
+    }
+
+    /// The fields of a record with named fields, in the order of the columns.
+    fn named_row(&self, mut fields: Map<String, Value>) -> Result<Vec<String>, DocumentError> {
+        if self.width.is_some() {
+            return Err(self.mismatch(
+                "a record with named fields cannot follow rows without names".to_string(),
+            ));
+        }
+        let Some(columns) = &self.columns else {
+            let mut row = Vec::with_capacity(fields.len());
+            for (name, value) in fields {
+                row.push(self.cell(&name, value)?);
+            }
+            return Ok(row);
+        };
+        if let Some(extra) = fields.keys().find(|name| !columns.contains(name)) {
+            return Err(self.mismatch(format!(
+                "field `{}` has no column, the columns are {}",
+                extra,
+                columns.join(", ")
+            )));
+        }
+        let mut row = Vec::with_capacity(columns.len());
+        for column in columns {
+            let value = fields
+                .remove(column)
+                .ok_or_else(|| self.mismatch(format!("the record has no field `{}`", column)))?;
+            row.push(self.cell(column, value)?);
+        }
+        Ok(row)
+    }
+
+    /// The fields of a record without names, like a tuple.
+    fn positional_row(&self, values: Vec<Value>) -> Result<Vec<String>, DocumentError> {
+        let expected = self.columns.as_ref().map(Vec::len).or(self.width);
+        if let Some(expected) = expected.filter(|&len| len != values.len() && !self.flexible) {
+            return Err(self.mismatch(format!(
+                "the record has {} fields, but the rows before it have {}",
+                values.len(),
+                expected
+            )));
+        }
+        values
+            .into_iter()
+            .enumerate()
+            .map(|(column, value)| self.cell(&column.to_string(), value))
+            .collect()
+    }
+}
+
+impl<T: Record> RecordWriter<T> for CsvRecordWriter {
+    fn write_record(&mut self, record: &T) -> Result<(), DocumentError> {
+        let value = serde_json::to_value(record).map_err(|err| self.mismatch(err.to_string()))?;
+        let csv_error = |err| csv_error(&self.file_name, err);
+        match value {
+            Value::Object(fields) => {
+                let names: Vec<String> = fields.keys().cloned().collect();
+                let row = self.named_row(fields)?;
+                if self.columns.is_none() {
+                    if self.has_headers {
+                        self.wtr.write_record(&names).map_err(csv_error)?;
+                    }
+                    self.columns = Some(names);
+                }
+                self.wtr.write_record(&row).map_err(csv_error)
+            }
+            value => {
+                let values = match value {
+                    Value::Array(values) => values,
+                    value => vec![value],
+                };
+                let row = self.positional_row(values)?;
+                if self.columns.is_none() {
+                    self.width.get_or_insert(row.len());
+                }
+                self.wtr.write_record(&row).map_err(csv_error)
+            }
+        }
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), DocumentError> {
+        let file_name = self.file_name;
+        self.wtr
+            .flush()
+            .map_err(|err| DocumentError::io(&file_name, err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creational::factory_method::DynamicRecord;
+
+    fn cache_key(processor: &CsvProcessor) -> String {
+        DocumentProcessor::<DynamicRecord>::cache_key(processor)
+    }
+
+    #[test]
+    fn dialects_read_differently_so_they_are_cached_apart() {
+        let semicolons = CsvProcessor::new(CsvDialect {
+            delimiter: b';',
+            ..CsvDialect::default()
+        });
+        assert_ne!(cache_key(&CsvProcessor::default()), cache_key(&semicolons));
+        assert_eq!(
+            cache_key(&CsvProcessor::default()),
+            cache_key(&CsvProcessor::default())
+        );
+    }
+
+    #[test]
+    fn dialects_are_detected_from_the_start_of_the_file() {
+        let cases: [(&str, &str, CsvDialect); 8] = [
+            (
+                "plain csv",
+                "name,age\nann,31\nbob,42\n",
+                CsvDialect::default(),
+            ),
+            (
+                "tsv",
+                "name\tage\tcity\nann\t31\tOslo, Norway\nbob\t42\tLyon\n",
+                CsvDialect {
+                    delimiter: b'\t',
+                    ..CsvDialect::default()
+                },
+            ),
+            (
+                "spreadsheet export with ; and decimal commas",
+                "name;price\r\nnut;1,50\r\nbolt;0,25\r\n",
+                CsvDialect {
+                    delimiter: b';',
+                    ..CsvDialect::default()
+                },
+            ),
+            (
+                "headerless numbers",
+                "1,2.5,3\n4,5.5,6\n7,8.5,9\n",
+                CsvDialect {
+                    has_headers: false,
+                    ..CsvDialect::default()
+                },
+            ),
+            (
+                "# comments",
+                "# exported by hand\nname|age\n# no age known\nann|\nbob|42\n",
+                CsvDialect {
+                    delimiter: b'|',
+                    comment: Some(b'#'),
+                    ..CsvDialect::default()
+                },
+            ),
+            (
+                "' quotes",
+                "'name','city'\n'ann','Oslo, Norway'\n'bob','Lyon'\n",
+                CsvDialect {
+                    quote: b'\'',
+                    ..CsvDialect::default()
+                },
+            ),
+            (
+                "padded fields with \\ escapes",
+                "name, quote\nann, \"say \\\"hi\\\"\"\n",
+                CsvDialect {
+                    escape: Some(b'\\'),
+                    trim: true,
+                    ..CsvDialect::default()
+                },
+            ),
+            (
+                "ragged rows",
+                "a,b,c\n1,2\n3,4,5\n",
+                CsvDialect {
+                    flexible: true,
+                    ..CsvDialect::default()
+                },
+            ),
+        ];
+        for (name, head, expected) in cases {
+            assert_eq!(
+                CsvDialect::detect_from(head.as_bytes()),
+                expected,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn header_rows_are_told_apart_from_data() {
+        let cases: [(&str, bool); 7] = [
+            ("name,age\nann,31\n", true),
+            ("id,score\n1,2.5\n2,3\n", true),
+            ("1,2.5\n2,3\n", false),
+            ("ann,31\nbob,42\n", false),
+            ("'1','x'\n'2','y'\n", false),
+            ("name,age\n", true),
+            ("", true),
+        ];
+        for (text, expected) in cases {
+            let rows: Vec<Vec<&[u8]>> = text
+                .lines()
+                .map(|line| split_unquoted(line.as_bytes(), b',', b'\''))
+                .collect();
+            assert_eq!(looks_like_header(&rows), expected, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn delimiters_inside_quotes_do_not_split() {
+        let cases: [(&str, u8, u8, &[&str]); 6] = [
+            ("a,b,c", b',', b'"', &["a", "b", "c"]),
+            ("\"a,b\",c", b',', b'"', &["\"a,b\"", "c"]),
+            ("'a;b';c", b';', b'\'', &["'a;b'", "c"]),
+            ("\"a\"\"b,c\",d", b',', b'"', &["\"a\"\"b,c\"", "d"]),
+            ("a\t\tb", b'\t', b'"', &["a", "", "b"]),
+            ("", b',', b'"', &[""]),
+        ];
+        for (line, delimiter, quote, expected) in cases {
+            let fields: Vec<&[u8]> = expected.iter().map(|field| field.as_bytes()).collect();
+            assert_eq!(
+                split_unquoted(line.as_bytes(), delimiter, quote),
+                fields,
+                "{:?}",
+                line
+            );
+            assert_eq!(
+                count_unquoted(line.as_bytes(), delimiter, quote),
+                fields.len() - 1
+            );
+        }
+    }
+}
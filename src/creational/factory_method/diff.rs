@@ -0,0 +1,305 @@
+use super::{DocumentEditor, DocumentError, Location, Record, field_at};
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+};
+
+/// A record only one of the two documents has. `index` counts the records of that document
+/// from zero, `key` holds its key fields when the diff has any.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UnmatchedRecord {
+    pub index: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub key: Vec<Value>,
+    pub record: Value,
+}
+
+/// A field that differs between two matched records. A side is `None` when the
+/// record there does not have the field at all.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldChange {
+    /// A dotted path like `address.zip`, with `[i]` for the items of a list.
+    pub field: String,
+    pub left: Option<Value>,
+    pub right: Option<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChangedRecord {
+    pub left_index: usize,
+    pub right_index: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub key: Vec<Value>,
+    pub fields: Vec<FieldChange>,
+}
+
+/// How the records of the right document differ from those of the left one.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DocumentDiff {
+    pub left: String,
+    pub right: String,
+    /// The fields records are matched on. Without any, records are matched by position.
+    pub keys: Vec<String>,
+    pub added: Vec<UnmatchedRecord>,
+    pub removed: Vec<UnmatchedRecord>,
+    pub changed: Vec<ChangedRecord>,
+    pub unchanged: usize,
+}
+
+impl DocumentDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// The diff as pretty printed json, for tools that want to read it.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a diff only holds json values")
+    }
+
+    fn describe_key(&self, key: &[Value]) -> String {
+        let fields: Vec<String> = self
+            .keys
+            .iter()
+            .zip(key)
+            .map(|(field, value)| format!("{} = {}", field, value))
+            .collect();
+        if fields.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", fields.join(", "))
+        }
+    }
+}
+
+/// Prints the diff as text, in the spirit of a unified diff.
+impl fmt::Display for DocumentDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "--- {}", self.left)?;
+        writeln!(f, "+++ {}", self.right)?;
+        for changed in &self.changed {
+            let index = if changed.left_index == changed.right_index {
+                changed.left_index.to_string()
+            } else {
+                format!("{} -> {}", changed.left_index, changed.right_index)
+            };
+            writeln!(f, "~ record {}{}", index, self.describe_key(&changed.key))?;
+            for field in &changed.fields {
+                let side = |value: &Option<Value>| {
+                    value
+                        .as_ref()
+                        .map_or_else(|| "missing".to_string(), Value::to_string)
+                };
+                writeln!(
+                    f,
+                    "    {}: {} -> {}",
+                    field.field,
+                    side(&field.left),
+                    side(&field.right)
+                )?;
+            }
+        }
+        for (sign, records) in [("-", &self.removed), ("+", &self.added)] {
+            for unmatched in records {
+                writeln!(
+                    f,
+                    "{} record {}{}: {}",
+                    sign,
+                    unmatched.index,
+                    self.describe_key(&unmatched.key),
+                    unmatched.record
+                )?;
+            }
+        }
+        write!(
+            f,
+            "{} added, {} removed, {} changed, {} unchanged",
+            self.added.len(),
+            self.removed.len(),
+            self.changed.len(),
+            self.unchanged
+        )
+    }
+}
+
+/// Compares the records of two documents, which can be in different formats, e.g.
+/// `data.csv` against `data.json`. Records are matched on the `keys` fields, dotted paths
+/// allowed, or by position when there are none. Records sharing a key are matched in order.
+///
+/// A number or boolean equals the text that spells it, like `30` and `"30"`, and null
+/// equals empty text, so a csv document, whose cells are all text, can be diffed against json.
+pub fn diff<T: Record>(
+    left: &DocumentEditor<T>,
+    right: &DocumentEditor<T>,
+    keys: &[&str],
+) -> Result<DocumentDiff, DocumentError> {
+    let left_records = load(left)?;
+    let right_records = load(right)?;
+    let keys: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+    let mut diff = DocumentDiff {
+        left: left.file_name.clone(),
+        right: right.file_name.clone(),
+        keys,
+        added: Vec::new(),
+        removed: Vec::new(),
+        changed: Vec::new(),
+        unchanged: 0,
+    };
+
+    let mut pairs = Vec::new();
+    let mut unmatched_right: Vec<usize> = Vec::new();
+    if diff.keys.is_empty() {
+        let len = left_records.len().max(right_records.len());
+        for index in 0..len {
+            match (index < left_records.len(), index < right_records.len()) {
+                (true, true) => pairs.push((index, index)),
+                (true, false) => diff.removed.push(unmatched(&left_records, index, &[])),
+                (false, _) => unmatched_right.push(index),
+            }
+        }
+    } else {
+        let mut by_key: HashMap<Vec<String>, VecDeque<usize>> = HashMap::new();
+        for (index, record) in right_records.iter().enumerate() {
+            by_key
+                .entry(key_text(record, &diff.keys))
+                .or_default()
+                .push_back(index);
+        }
+        let mut matched = vec![false; right_records.len()];
+        for (index, record) in left_records.iter().enumerate() {
+            let key = key_text(record, &diff.keys);
+            match by_key.get_mut(&key).and_then(VecDeque::pop_front) {
+                Some(right_index) => {
+                    matched[right_index] = true;
+                    pairs.push((index, right_index));
+                }
+                None => diff
+                    .removed
+                    .push(unmatched(&left_records, index, &diff.keys)),
+            }
+        }
+        unmatched_right.extend((0..right_records.len()).filter(|&index| !matched[index]));
+    }
+    for index in unmatched_right {
+        diff.added
+            .push(unmatched(&right_records, index, &diff.keys));
+    }
+
+    for (left_index, right_index) in pairs {
+        let mut fields = Vec::new();
+        diff_values(
+            "",
+            Some(&left_records[left_index]),
+            Some(&right_records[right_index]),
+            &mut fields,
+        );
+        if fields.is_empty() {
+            diff.unchanged += 1;
+        } else {
+            diff.changed.push(ChangedRecord {
+                left_index,
+                right_index,
+                key: key_values(&left_records[left_index], &diff.keys),
+                fields,
+            });
+        }
+    }
+    Ok(diff)
+}
+
+/// Every record of the editor's document, as its serde representation.
+fn load<T: Record>(editor: &DocumentEditor<T>) -> Result<Vec<Value>, DocumentError> {
+    editor
+        .read_records()?
+        .map(|record| {
+            serde_json::to_value(record?).map_err(|err| {
+                DocumentError::schema_mismatch(
+                    &editor.file_name,
+                    Location::default(),
+                    err.to_string(),
+                )
+            })
+        })
+        .collect()
+}
+
+fn unmatched(records: &[Value], index: usize, keys: &[String]) -> UnmatchedRecord {
+    UnmatchedRecord {
+        index,
+        key: key_values(&records[index], keys),
+        record: records[index].clone(),
+    }
+}
+
+fn key_values(record: &Value, keys: &[String]) -> Vec<Value> {
+    keys.iter()
+        .map(|key| field_at(record, key).cloned().unwrap_or(Value::Null))
+        .collect()
+}
+
+/// The key compared as text, so a csv key matches the same number in json.
+fn key_text(record: &Value, keys: &[String]) -> Vec<String> {
+    key_values(record, keys)
+        .iter()
+        .map(|value| as_text(value).unwrap_or_else(|| value.to_string()))
+        .collect()
+}
+
+fn as_text(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => Some(String::new()),
+        Value::Bool(value) => Some(value.to_string()),
+        Value::Number(value) => Some(value.to_string()),
+        Value::String(value) => Some(value.clone()),
+        Value::Array(_) | Value::Object(_) => None,
+    }
+}
+
+fn same(left: &Value, right: &Value) -> bool {
+    left == right || as_text(left).is_some_and(|left| as_text(right) == Some(left))
+}
+
+/// Walks both values side by side and collects the leaves that differ.
+fn diff_values(
+    path: &str,
+    left: Option<&Value>,
+    right: Option<&Value>,
+    changes: &mut Vec<FieldChange>,
+) {
+    let child = |name: &str| {
+        if path.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}.{}", path, name)
+        }
+    };
+    match (left, right) {
+        (Some(Value::Object(left)), Some(Value::Object(right))) => {
+            for (name, value) in left {
+                diff_values(&child(name), Some(value), right.get(name), changes);
+            }
+            for (name, value) in right {
+                if !left.contains_key(name) {
+                    diff_values(&child(name), None, Some(value), changes);
+                }
+            }
+        }
+        (Some(Value::Array(left)), Some(Value::Array(right))) => {
+            for index in 0..left.len().max(right.len()) {
+                diff_values(
+                    &format!("{}[{}]", path, index),
+                    left.get(index),
+                    right.get(index),
+                    changes,
+                );
+            }
+        }
+        (Some(left), Some(right)) if same(left, right) => {}
+        _ => changes.push(FieldChange {
+            field: path.to_string(),
+            left: left.cloned(),
+            right: right.cloned(),
+        }),
+    }
+}
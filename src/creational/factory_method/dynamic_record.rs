@@ -0,0 +1,60 @@
+use serde::{
+    Deserialize, Deserializer, Serialize, Serializer,
+    de::{MapAccess, SeqAccess, Visitor},
+};
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// A record whose fields are only known at runtime, for tools that have to handle any
+/// document without a record type of their own. Fields keep the order of the source, and
+/// the types it gives them. Csv and xml have none, so their fields stay text exactly as
+/// written, a zip code like `00501` included. Rows of a csv file without headers are keyed
+/// by column number, starting at `"0"`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DynamicRecord(pub Map<String, Value>);
+
+impl DynamicRecord {
+    pub fn get(&self, field: &str) -> Option<&Value> {
+        self.0.get(field)
+    }
+}
+
+impl Serialize for DynamicRecord {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for DynamicRecord {
+    /// Asks for a map, which every format answers with the fields of a record.
+    /// Rows without headers come as a sequence instead.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(DynamicRecordVisitor)
+    }
+}
+
+struct DynamicRecordVisitor;
+
+impl<'de> Visitor<'de> for DynamicRecordVisitor {
+    type Value = DynamicRecord;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a record with named or numbered fields")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<DynamicRecord, A::Error> {
+        let mut fields = Map::new();
+        while let Some((key, value)) = map.next_entry::<String, Value>()? {
+            fields.insert(key, value);
+        }
+        Ok(DynamicRecord(fields))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<DynamicRecord, A::Error> {
+        let mut fields = Map::new();
+        while let Some(value) = seq.next_element::<Value>()? {
+            fields.insert(fields.len().to_string(), value);
+        }
+        Ok(DynamicRecord(fields))
+    }
+}
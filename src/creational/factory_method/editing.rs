@@ -0,0 +1,266 @@
+use super::{
+    DocumentEditor, DocumentError, Location, Record, check_record, field_at, history::Change,
+    same_value,
+};
+use serde_json::Value;
+use std::fmt;
+
+/// Picks a record of a loaded document, by its position or by the value of a key field.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordRef {
+    /// Counts records from zero.
+    Index(usize),
+    /// The first record whose `field` holds `value`. `field` can be a dotted path. Text
+    /// that spells a number matches that number, so `2` finds the csv row with id `2`.
+    Key { field: String, value: Value },
+}
+
+impl RecordRef {
+    pub fn key(field: &str, value: impl Into<Value>) -> Self {
+        RecordRef::Key {
+            field: field.to_string(),
+            value: value.into(),
+        }
+    }
+}
+
+impl From<usize> for RecordRef {
+    fn from(index: usize) -> Self {
+        RecordRef::Index(index)
+    }
+}
+
+impl fmt::Display for RecordRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordRef::Index(index) => write!(f, "{}", index),
+            RecordRef::Key { field, value } => write!(f, "with {} = {}", field, value),
+        }
+    }
+}
+
+impl<T: Record> DocumentEditor<T> {
+    /// Reads the whole document into memory, dropping any edits that were not written back.
+    /// Records are checked against the editor's validator as they are read.
+    pub fn load(&mut self) -> Result<(), DocumentError> {
+        let records = self.read_records()?.collect::<Result<Vec<T>, _>>()?;
+        self.records = Some(records);
+        self.history.clear();
+        Ok(())
+    }
+
+    /// The records held in memory, loading the document first if it has not been.
+    pub fn records(&mut self) -> Result<&[T], DocumentError> {
+        Ok(self.loaded()?)
+    }
+
+    /// Whether the records in memory have edits that are not written back yet.
+    pub fn is_dirty(&self) -> bool {
+        self.history.is_dirty()
+    }
+
+    /// Adds `record` after the last one.
+    pub fn append(&mut self, record: T) -> Result<(), DocumentError> {
+        let index = self.loaded()?.len();
+        self.insert(index, record)
+    }
+
+    /// Puts `record` at `index`, moving the records from there on back by one.
+    /// `index` may be the number of records, which appends.
+    pub fn insert(&mut self, index: usize, record: T) -> Result<(), DocumentError> {
+        if index > self.loaded()?.len() {
+            return Err(self.not_found(RecordRef::Index(index)));
+        }
+        let record = check_record(self.validator.as_deref(), &self.file_name, index, record)?;
+        self.edit(Change::Insert { index, record });
+        Ok(())
+    }
+
+    /// Sets `field`, a field name or dotted path, of the record `at` points to.
+    /// The record goes through its serde representation, so this works for any record
+    /// type, but the new value has to fit the field's type.
+    pub fn update(
+        &mut self,
+        at: impl Into<RecordRef>,
+        field: &str,
+        value: impl Into<Value>,
+    ) -> Result<(), DocumentError> {
+        let index = self.position(&at.into())?;
+        let value = value.into();
+        let record = set_field(&self.loaded()?[index], field, value)
+            .map_err(|message| self.mismatch(index, message))?;
+        let record = check_record(self.validator.as_deref(), &self.file_name, index, record)?;
+        self.edit(Change::Replace { index, record });
+        Ok(())
+    }
+
+    /// Replaces the record `at` points to with `record`.
+    pub fn replace(&mut self, at: impl Into<RecordRef>, record: T) -> Result<(), DocumentError> {
+        let index = self.position(&at.into())?;
+        let record = check_record(self.validator.as_deref(), &self.file_name, index, record)?;
+        self.edit(Change::Replace { index, record });
+        Ok(())
+    }
+
+    /// Removes the record `at` points to. The record is kept in the history, so the
+    /// delete can be undone.
+    pub fn delete(&mut self, at: impl Into<RecordRef>) -> Result<(), DocumentError> {
+        let index = self.position(&at.into())?;
+        self.edit(Change::Remove { index });
+        Ok(())
+    }
+
+    /// Where the record `at` points to is, loading the document first if needed.
+    pub fn position(&mut self, at: &RecordRef) -> Result<usize, DocumentError> {
+        let records = self.loaded()?;
+        let index = match at {
+            RecordRef::Index(index) => Some(*index).filter(|&index| index < records.len()),
+            RecordRef::Key { field, value } => records.iter().position(|record| {
+                serde_json::to_value(record).is_ok_and(|record| {
+                    field_at(&record, field).is_some_and(|held| same_value(held, value))
+                })
+            }),
+        };
+        index.ok_or_else(|| self.not_found(at.clone()))
+    }
+
+    /// Saves the records held in memory when they have been edited since they were loaded
+    /// or last written back. Returns whether anything was written.
+    pub fn write_back(&mut self) -> Result<bool, DocumentError> {
+        let Some(records) = self.records.as_deref().filter(|_| self.history.is_dirty()) else {
+            return Ok(false);
+        };
+        self.save(records)?;
+        self.history.mark_saved();
+        Ok(true)
+    }
+
+    /// Keeps up to `depth` edits to undo, dropping the oldest ones beyond that.
+    /// A depth of zero turns undo off.
+    pub fn with_history_depth(mut self, depth: usize) -> Self {
+        self.history.set_depth(depth);
+        self
+    }
+
+    /// Reverts the last edit, or the last transaction as a whole.
+    /// Returns false when there is nothing to undo, or a transaction is still running.
+    pub fn undo(&mut self) -> bool {
+        match &mut self.records {
+            Some(records) => self.history.undo(records),
+            None => false,
+        }
+    }
+
+    /// Makes the last undone edit again. A new edit drops everything that could be redone.
+    pub fn redo(&mut self) -> bool {
+        match &mut self.records {
+            Some(records) => self.history.redo(records),
+            None => false,
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.history.can_undo()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.history.can_redo()
+    }
+
+    /// Runs `edits` as one transaction, which is undone and redone as a single edit.
+    /// When `edits` fails, everything it changed is reverted before the error is returned.
+    /// A transaction started inside another one becomes part of it.
+    pub fn transaction<R, F>(&mut self, edits: F) -> Result<R, DocumentError>
+    where
+        F: FnOnce(&mut Self) -> Result<R, DocumentError>,
+    {
+        self.loaded()?;
+        let mark = self.history.begin();
+        match edits(self) {
+            Ok(result) => {
+                self.history.commit(mark);
+                Ok(result)
+            }
+            Err(err) => {
+                let records = self.records.get_or_insert_with(Vec::new);
+                self.history.rollback(records, mark);
+                Err(err)
+            }
+        }
+    }
+
+    fn loaded(&mut self) -> Result<&mut Vec<T>, DocumentError> {
+        if self.records.is_none() {
+            self.load()?;
+        }
+        Ok(self.records.get_or_insert_with(Vec::new))
+    }
+
+    fn edit(&mut self, change: Change<T>) {
+        let records = self.records.get_or_insert_with(Vec::new);
+        self.history.apply(records, change);
+    }
+
+    fn not_found(&self, record: RecordRef) -> DocumentError {
+        DocumentError::RecordNotFound {
+            path: self.file_name.clone(),
+            record,
+        }
+    }
+
+    fn mismatch(&self, index: usize, message: String) -> DocumentError {
+        DocumentError::schema_mismatch(
+            &self.file_name,
+            Location::default(),
+            format!("record {}: {}", index, message),
+        )
+    }
+}
+
+/// `record` with `field` set to `value`, or why the record type cannot take it.
+fn set_field<T: Record>(record: &T, field: &str, value: Value) -> Result<T, String> {
+    let mut tree = serde_json::to_value(record).map_err(|err| err.to_string())?;
+    let mut slot = &mut tree;
+    for name in field.split('.') {
+        slot = match slot {
+            Value::Object(fields) => fields.entry(name).or_insert(Value::Null),
+            _ => return Err(format!("`{}` is not inside a record", field)),
+        };
+    }
+    *slot = value.clone();
+    let updated: T = serde_json::from_value(tree).map_err(|err| format!("`{}`: {}", field, err))?;
+    // serde skips fields a type does not know, so a misspelled name would be lost silently.
+    let kept = serde_json::to_value(&updated).map_err(|err| err.to_string())?;
+    match field_at(&kept, field) {
+        None => Err(format!("the record has no field `{}`", field)),
+        Some(kept) if *kept != value => Err(format!("`{}` cannot hold {}", field, value)),
+        Some(_) => Ok(updated),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creational::factory_method::{
+        DocumentEditorFactory, DocumentType, DynamicRecord, test_files::TempDir,
+    };
+
+    #[test]
+    fn keys_find_csv_rows_by_number() {
+        let dir = TempDir::new();
+        let path = dir.write("people.csv", "id,name\n1,ann\n2,bob\n");
+        let mut editor =
+            DocumentEditorFactory::create_editor::<DynamicRecord>(path, DocumentType::Csv);
+        assert_eq!(editor.position(&RecordRef::key("id", 2)).unwrap(), 1);
+        assert_eq!(editor.position(&RecordRef::key("id", "2")).unwrap(), 1);
+        editor
+            .update(RecordRef::key("id", 2), "name", "bea")
+            .unwrap();
+        editor.write_back().unwrap();
+        assert_eq!(dir.read("people.csv"), b"id,name\n1,ann\n2,bea\n");
+        assert!(matches!(
+            editor.position(&RecordRef::key("id", 3)),
+            Err(DocumentError::RecordNotFound { .. })
+        ));
+    }
+}
@@ -0,0 +1,423 @@
+use super::{Compression, SNIFF_LEN, peek_head};
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+};
+
+/// The character encoding of a document. Processors only ever see UTF-8,
+/// `DocumentEditor` converts from and to the others around them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    /// UTF-8 starting with a byte order mark, as spreadsheet programs like to write csv.
+    Utf8Bom,
+    /// UTF-16 is always written with a byte order mark, but also read without one.
+    Utf16Le,
+    Utf16Be,
+    /// ISO-8859-1, where every byte is the character with the same number.
+    Latin1,
+}
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+const UTF16_LE_BOM: &[u8] = b"\xFF\xFE";
+const UTF16_BE_BOM: &[u8] = b"\xFE\xFF";
+const BOM: char = '\u{FEFF}';
+
+impl Encoding {
+    /// Recognizes the encoding by a byte order mark, and failing that by the bytes
+    /// themselves: text in UTF-16 has a zero in every other byte as long as it is mostly
+    /// ascii, and text that is not valid UTF-8 is taken to be Latin-1.
+    pub fn from_head(head: &[u8]) -> Encoding {
+        if head.starts_with(UTF8_BOM) {
+            return Encoding::Utf8Bom;
+        } else if head.starts_with(UTF16_LE_BOM) {
+            return Encoding::Utf16Le;
+        } else if head.starts_with(UTF16_BE_BOM) {
+            return Encoding::Utf16Be;
+        }
+        let units = head.len() / 2;
+        let zeros = |start: usize| {
+            head.iter()
+                .skip(start)
+                .step_by(2)
+                .filter(|&&byte| byte == 0)
+                .count()
+        };
+        let (even, odd) = (zeros(0), zeros(1));
+        if units > 0 && odd * 2 > units && even * 10 < units {
+            return Encoding::Utf16Le;
+        } else if units > 0 && even * 2 > units && odd * 10 < units {
+            return Encoding::Utf16Be;
+        }
+        match std::str::from_utf8(head) {
+            Ok(_) => Encoding::Utf8,
+            // A character cut in half at the end of the head is still UTF-8.
+            Err(err) if err.error_len().is_none() => Encoding::Utf8,
+            Err(_) => Encoding::Latin1,
+        }
+    }
+
+    /// The encoding of an existing file, looked at after decompressing it.
+    /// A file that does not exist yet is UTF-8.
+    pub fn detect(file_name: &str) -> io::Result<Encoding> {
+        match File::open(file_name) {
+            Ok(file) => {
+                let mut head = Vec::with_capacity(SNIFF_LEN);
+                Compression::decode(Box::new(file))?
+                    .take(SNIFF_LEN as u64)
+                    .read_to_end(&mut head)?;
+                Ok(Encoding::from_head(&head))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Encoding::Utf8),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Detects the encoding of `source` from its first bytes and converts it to UTF-8.
+    pub(super) fn decode<'a>(
+        source: Box<dyn Read + 'a>,
+    ) -> io::Result<(Encoding, Box<dyn Read + 'a>)> {
+        let (head, source) = peek_head(source, SNIFF_LEN)?;
+        let encoding = Encoding::from_head(&head);
+        Ok((encoding, encoding.decoder(source)?))
+    }
+
+    /// Converts `source` from this encoding to UTF-8, dropping a byte order mark.
+    pub(super) fn decoder<'a>(self, source: Box<dyn Read + 'a>) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Encoding::Utf8 | Encoding::Utf8Bom => {
+                let (head, mut source) = peek_head(source, UTF8_BOM.len())?;
+                if head == UTF8_BOM {
+                    // The peeked bytes were put back in front, so the mark is read past here.
+                    source.read_exact(&mut [0; UTF8_BOM.len()])?;
+                }
+                source
+            }
+            _ => Box::new(Decoder {
+                source,
+                encoding: self,
+                input: Vec::new(),
+                output: Vec::new(),
+                read: 0,
+                started: false,
+                failed: false,
+            }),
+        })
+    }
+
+    /// Converts the UTF-8 the writers produce to this encoding, byte order mark included.
+    pub(super) fn encoder(self, sink: Box<dyn Write>) -> Box<dyn Write> {
+        match self {
+            Encoding::Utf8 => sink,
+            _ => Box::new(Encoder {
+                sink,
+                encoding: self,
+                pending: Vec::new(),
+                started: false,
+            }),
+        }
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads UTF-16 or Latin-1 and hands out UTF-8. Bytes that do not make up a whole
+/// character yet wait in `input` for the next read.
+struct Decoder<'a> {
+    source: Box<dyn Read + 'a>,
+    encoding: Encoding,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    read: usize,
+    started: bool,
+    /// Set once the text turned out not to be valid, after which there is nothing more to read.
+    failed: bool,
+}
+
+impl Decoder<'_> {
+    /// Decodes the next chunk of the source. Returns false at the end of it.
+    fn fill(&mut self) -> io::Result<bool> {
+        let mut chunk = [0; 8 * 1024];
+        let len = self.source.read(&mut chunk)?;
+        if len == 0 {
+            if !self.input.is_empty() {
+                self.failed = true;
+                return Err(invalid(format!(
+                    "the text ends in the middle of a {:?} character",
+                    self.encoding
+                )));
+            }
+            return Ok(false);
+        }
+        self.input.extend_from_slice(&chunk[..len]);
+        self.output.clear();
+        self.read = 0;
+
+        let mut text = String::new();
+        match self.encoding {
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let mut units: Vec<u16> = self
+                    .input
+                    .chunks_exact(2)
+                    .map(|pair| match self.encoding {
+                        Encoding::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                        _ => u16::from_be_bytes([pair[0], pair[1]]),
+                    })
+                    .collect();
+                // A high surrogate needs the unit after it, which may not have been read yet.
+                let split = units
+                    .last()
+                    .is_some_and(|unit| (0xD800..0xDC00).contains(unit));
+                if split {
+                    units.pop();
+                }
+                for c in char::decode_utf16(units.iter().copied()) {
+                    match c {
+                        Ok(c) => text.push(c),
+                        Err(err) => {
+                            self.failed = true;
+                            return Err(invalid(format!("invalid UTF-16, {}", err)));
+                        }
+                    }
+                }
+                let used = units.len() * 2;
+                self.input.drain(..used);
+            }
+            _ => {
+                text.extend(self.input.drain(..).map(char::from));
+            }
+        }
+        if !self.started && !text.is_empty() {
+            self.started = true;
+            if text.starts_with(BOM) {
+                text.remove(0);
+            }
+        }
+        self.output = text.into_bytes();
+        Ok(true)
+    }
+}
+
+impl Read for Decoder<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.read == self.output.len() {
+            if self.failed || !self.fill()? {
+                return Ok(0);
+            }
+        }
+        let len = buf.len().min(self.output.len() - self.read);
+        buf[..len].copy_from_slice(&self.output[self.read..self.read + len]);
+        self.read += len;
+        Ok(len)
+    }
+}
+
+/// Takes UTF-8 and writes it in another encoding. A character split across two
+/// writes waits in `pending` for the rest of it.
+struct Encoder {
+    sink: Box<dyn Write>,
+    encoding: Encoding,
+    pending: Vec<u8>,
+    started: bool,
+}
+
+impl Encoder {
+    fn start(&mut self) -> io::Result<()> {
+        if !self.started {
+            self.started = true;
+            let bom = match self.encoding {
+                Encoding::Utf8Bom => UTF8_BOM,
+                Encoding::Utf16Le => UTF16_LE_BOM,
+                Encoding::Utf16Be => UTF16_BE_BOM,
+                Encoding::Utf8 | Encoding::Latin1 => b"",
+            };
+            self.sink.write_all(bom)?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for Encoder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.start()?;
+        self.pending.extend_from_slice(buf);
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(text) => text.len(),
+            Err(err) if err.error_len().is_none() => err.valid_up_to(),
+            Err(err) => {
+                return Err(invalid(format!(
+                    "the writer produced invalid UTF-8, {}",
+                    err
+                )));
+            }
+        };
+        let text = std::str::from_utf8(&self.pending[..valid]).expect("checked above");
+        let mut bytes = Vec::with_capacity(text.len() * 2);
+        for c in text.chars() {
+            match self.encoding {
+                Encoding::Utf8 | Encoding::Utf8Bom => {
+                    bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes())
+                }
+                Encoding::Utf16Le | Encoding::Utf16Be => {
+                    for unit in c.encode_utf16(&mut [0; 2]) {
+                        bytes.extend_from_slice(&match self.encoding {
+                            Encoding::Utf16Le => unit.to_le_bytes(),
+                            _ => unit.to_be_bytes(),
+                        });
+                    }
+                }
+                Encoding::Latin1 => match u8::try_from(c) {
+                    Ok(byte) => bytes.push(byte),
+                    Err(_) => {
+                        return Err(invalid(format!("{:?} cannot be written in Latin-1", c)));
+                    }
+                },
+            }
+        }
+        self.sink.write_all(&bytes)?;
+        self.pending.drain(..valid);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            return Err(invalid(
+                "the writer ended in the middle of a character".to_string(),
+            ));
+        }
+        self.start()?;
+        self.sink.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hands out at most `size` bytes per read, so characters land across chunk boundaries.
+    struct Trickle<'a> {
+        bytes: &'a [u8],
+        size: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = self.size.min(buf.len()).min(self.bytes.len());
+            buf[..len].copy_from_slice(&self.bytes[..len]);
+            self.bytes = &self.bytes[len..];
+            Ok(len)
+        }
+    }
+
+    fn utf16(text: &str, encoding: Encoding) -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|unit| match encoding {
+                Encoding::Utf16Le => unit.to_le_bytes(),
+                _ => unit.to_be_bytes(),
+            })
+            .collect()
+    }
+
+    fn read_all(source: io::Result<Box<dyn Read + '_>>) -> io::Result<String> {
+        let mut text = String::new();
+        source?.read_to_string(&mut text)?;
+        Ok(text)
+    }
+
+    /// Decodes `bytes` as `encoding` handed out in every chunk size up to 7.
+    fn decode_in_chunks(bytes: &[u8], encoding: Encoding) -> Vec<io::Result<String>> {
+        (1..=7)
+            .map(|size| read_all(encoding.decoder(Box::new(Trickle { bytes, size }))))
+            .collect()
+    }
+
+    const TEXT: &str = "a😀b, ünï\n𝄞";
+
+    #[test]
+    fn decodes_utf16_le_and_be_split_anywhere() {
+        for encoding in [Encoding::Utf16Le, Encoding::Utf16Be] {
+            for text in decode_in_chunks(&utf16(TEXT, encoding), encoding) {
+                assert_eq!(text.unwrap(), TEXT, "{:?}", encoding);
+            }
+        }
+    }
+
+    #[test]
+    fn drops_the_utf16_byte_order_mark_split_anywhere() {
+        for encoding in [Encoding::Utf16Le, Encoding::Utf16Be] {
+            let bytes = utf16(&format!("{}{}", BOM, TEXT), encoding);
+            for text in decode_in_chunks(&bytes, encoding) {
+                assert_eq!(text.unwrap(), TEXT, "{:?}", encoding);
+            }
+            let detected = Encoding::decode(Box::new(Trickle {
+                bytes: &bytes,
+                size: 1,
+            }))
+            .map(|(detected, source)| {
+                assert_eq!(detected, encoding);
+                source
+            });
+            assert_eq!(read_all(detected).unwrap(), TEXT);
+        }
+    }
+
+    #[test]
+    fn a_trailing_odd_byte_or_lone_surrogate_is_invalid() {
+        let mut odd = utf16("ab", Encoding::Utf16Le);
+        odd.push(b'c');
+        for text in decode_in_chunks(&odd, Encoding::Utf16Le) {
+            assert_eq!(text.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+        let mut cut = utf16("a😀", Encoding::Utf16Be);
+        cut.truncate(cut.len() - 2);
+        for text in decode_in_chunks(&cut, Encoding::Utf16Be) {
+            assert_eq!(text.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn reads_after_an_error_find_the_end() {
+        let mut bytes = utf16("ab", Encoding::Utf16Le);
+        bytes.extend_from_slice(&0xDC00u16.to_le_bytes());
+        bytes.extend(utf16("cd", Encoding::Utf16Le));
+        bytes.push(b'e');
+        for size in 1..=7 {
+            let mut decoder = Encoding::Utf16Le
+                .decoder(Box::new(Trickle {
+                    bytes: &bytes,
+                    size,
+                }))
+                .unwrap();
+            let mut text = Vec::new();
+            let err = decoder.read_to_end(&mut text).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert!(err.to_string().contains("invalid UTF-16"), "{}", err);
+            assert_eq!(decoder.read(&mut [0; 16]).unwrap(), 0);
+            assert_eq!(decoder.read(&mut [0; 16]).unwrap(), 0);
+        }
+    }
+
+    #[test]
+    fn decodes_latin1_byte_by_byte() {
+        let bytes = b"caf\xe9, \xfcber \xa3\xff";
+        let detected = Encoding::decode(Box::new(Trickle { bytes, size: 1 }));
+        let detected = detected.map(|(encoding, source)| {
+            assert_eq!(encoding, Encoding::Latin1);
+            source
+        });
+        assert_eq!(read_all(detected).unwrap(), "café, über £ÿ");
+        for text in decode_in_chunks(bytes, Encoding::Latin1) {
+            assert_eq!(text.unwrap(), "café, über £ÿ");
+        }
+    }
+
+    #[test]
+    fn drops_the_utf8_byte_order_mark() {
+        let bytes = [UTF8_BOM, TEXT.as_bytes()].concat();
+        for text in decode_in_chunks(&bytes, Encoding::Utf8Bom) {
+            assert_eq!(text.unwrap(), TEXT);
+        }
+    }
+}
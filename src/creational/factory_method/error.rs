@@ -0,0 +1,219 @@
+use super::{RecordRef, Violation};
+use std::{error::Error, fmt, io};
+
+/// Where in a document an error was found. Formats fill in what they know, so any
+/// part can be missing. Lines and columns start at 1, byte offsets at 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Location {
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub offset: Option<u64>,
+}
+
+impl Location {
+    pub fn is_known(&self) -> bool {
+        self.line.is_some() || self.column.is_some() || self.offset.is_some()
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(line) = self.line {
+            parts.push(format!("line {}", line));
+        }
+        if let Some(column) = self.column {
+            parts.push(format!("column {}", column));
+        }
+        let offset = self.offset.map(|offset| format!("byte {}", offset));
+        match (parts.is_empty(), offset) {
+            (false, Some(offset)) => write!(f, "{} ({})", parts.join(", "), offset),
+            (true, Some(offset)) => f.write_str(&offset),
+            (_, None) => f.write_str(&parts.join(", ")),
+        }
+    }
+}
+
+/// Everything that can go wrong while reading or writing a document.
+/// Each variant carries the path of the document it is about.
+#[derive(Debug)]
+pub enum DocumentError {
+    /// The file could not be opened, read or written.
+    Io { path: String, source: io::Error },
+    /// The document is not well formed.
+    Parse {
+        path: String,
+        location: Location,
+        message: String,
+    },
+    /// The document holds no records at all.
+    EmptyDocument { path: String },
+    /// The document is well formed, but a record in it does not fit the record type,
+    /// or a record cannot be written in the document's format.
+    SchemaMismatch {
+        path: String,
+        location: Location,
+        message: String,
+    },
+    /// No processor can handle the document.
+    UnsupportedFormat { path: String, message: String },
+    /// A record was read fine but breaks the editor's validation rules.
+    /// `record` counts records from zero.
+    Validation {
+        path: String,
+        record: usize,
+        violations: Vec<Violation>,
+    },
+    /// An edit points at a record the document does not have.
+    RecordNotFound { path: String, record: RecordRef },
+}
+
+impl DocumentError {
+    pub fn io(path: &str, source: io::Error) -> Self {
+        DocumentError::Io {
+            path: path.to_string(),
+            source,
+        }
+    }
+
+    pub fn parse(path: &str, location: Location, message: impl Into<String>) -> Self {
+        DocumentError::Parse {
+            path: path.to_string(),
+            location,
+            message: message.into(),
+        }
+    }
+
+    pub fn schema_mismatch(path: &str, location: Location, message: impl Into<String>) -> Self {
+        DocumentError::SchemaMismatch {
+            path: path.to_string(),
+            location,
+            message: message.into(),
+        }
+    }
+
+    pub fn unsupported_format(path: &str, message: impl Into<String>) -> Self {
+        DocumentError::UnsupportedFormat {
+            path: path.to_string(),
+            message: message.into(),
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        match self {
+            DocumentError::Io { path, .. }
+            | DocumentError::Parse { path, .. }
+            | DocumentError::EmptyDocument { path }
+            | DocumentError::SchemaMismatch { path, .. }
+            | DocumentError::UnsupportedFormat { path, .. }
+            | DocumentError::Validation { path, .. }
+            | DocumentError::RecordNotFound { path, .. } => path,
+        }
+    }
+
+    /// Where in the document the error is, for the variants that point into one.
+    pub fn location(&self) -> Option<Location> {
+        match self {
+            DocumentError::Parse { location, .. }
+            | DocumentError::SchemaMismatch { location, .. } => Some(*location),
+            _ => None,
+        }
+    }
+
+    /// Points the error at another file. Writers only ever see the temp file that is
+    /// renamed over the document, which is not a name worth showing anyone.
+    pub(super) fn with_path(mut self, new_path: &str) -> Self {
+        match &mut self {
+            DocumentError::Io { path, .. }
+            | DocumentError::Parse { path, .. }
+            | DocumentError::EmptyDocument { path }
+            | DocumentError::SchemaMismatch { path, .. }
+            | DocumentError::UnsupportedFormat { path, .. }
+            | DocumentError::Validation { path, .. }
+            | DocumentError::RecordNotFound { path, .. } => *path = new_path.to_string(),
+        }
+        self
+    }
+}
+
+impl fmt::Display for DocumentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DocumentError::Io { path, source } => write!(f, "{}: {}", path, source),
+            DocumentError::Parse {
+                path,
+                location,
+                message,
+            }
+            | DocumentError::SchemaMismatch {
+                path,
+                location,
+                message,
+            } => {
+                write!(f, "{}: {}", path, message)?;
+                if location.is_known() {
+                    write!(f, " at {}", location)?;
+                }
+                Ok(())
+            }
+            DocumentError::EmptyDocument { path } => {
+                write!(f, "{}: the document holds no records", path)
+            }
+            DocumentError::UnsupportedFormat { path, message } => {
+                write!(f, "{}: {}", path, message)
+            }
+            DocumentError::Validation {
+                path,
+                record,
+                violations,
+            } => {
+                let violations: Vec<String> = violations.iter().map(ToString::to_string).collect();
+                write!(
+                    f,
+                    "{}: record {} is invalid, {}",
+                    path,
+                    record,
+                    violations.join("; ")
+                )
+            }
+            DocumentError::RecordNotFound { path, record } => {
+                write!(f, "{}: there is no record {}", path, record)
+            }
+        }
+    }
+}
+
+impl Error for DocumentError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DocumentError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// serde's own messages for a value that does not fit the type it is read into.
+/// Formats that cannot tell these apart from syntax errors look for them in the message,
+/// which may start with the path to the field, as in `address.zip: invalid type ..`.
+pub(super) fn is_schema_message(message: &str) -> bool {
+    let prefixes = [
+        "invalid type",
+        "invalid value",
+        "invalid length",
+        "unknown variant",
+        "unknown field",
+        "missing field",
+        "duplicate field",
+    ];
+    std::iter::once(message)
+        .chain(message.match_indices(": ").map(|(i, _)| &message[i + 2..]))
+        .any(|rest| prefixes.iter().any(|prefix| rest.starts_with(prefix)))
+}
+
+/// Drops the " at line L column C" that serde_json and serde_yaml append to their messages,
+/// since the position is reported through `Location` instead.
+pub(super) fn strip_position(message: &str) -> &str {
+    message
+        .rsplit_once(" at line ")
+        .map_or(message, |(message, _)| message)
+}
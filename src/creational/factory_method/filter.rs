@@ -0,0 +1,632 @@
+use super::{
+    DocumentEditor, DocumentError, Location, Record, RecordIter, as_number, field_at_names,
+};
+use regex::Regex;
+use serde_json::{Number, Value};
+use std::{cmp::Ordering, fmt};
+
+/// A parsed filter expression, like `age >= 18 and name ~ "^obv"`.
+///
+/// - Fields are names or dotted paths, `address.zip`. Names that are not plain words go
+///   in backticks, `` `first name` ``. A missing field is null.
+/// - Literals are numbers, text in double or single quotes, `true`, `false` and `null`.
+/// - `==`, `!=`, `<`, `<=`, `>`, `>=` compare values. Text that spells a number compares
+///   as that number, against a number or other such text, so `age > 30` works on csv cells
+///   as well and `"10" > "9"`. Other text compares as text. Ordering anything against null,
+///   or values of different kinds, is false.
+/// - `~` and `!~` match text against a regex, which has to be a quoted literal.
+/// - `and`, `or`, `not` and parentheses combine conditions. `not` and parentheses nest at
+///   most 64 deep. A field on its own is true when it holds `true`.
+/// - Null equals null and empty text, so `email == null` finds records without an email.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    source: String,
+    expr: Expr,
+}
+
+impl Filter {
+    /// Parses `expression`. A syntax error is a `DocumentError::Parse` with the expression
+    /// in place of a path and the column where it went wrong.
+    pub fn parse(expression: &str) -> Result<Filter, DocumentError> {
+        let tokens = tokenize(expression)?;
+        let mut parser = Parser {
+            source: expression,
+            tokens,
+            next: 0,
+            depth: 0,
+        };
+        let expr = parser.or()?;
+        parser.expect_end()?;
+        Ok(Filter {
+            source: expression.to_string(),
+            expr,
+        })
+    }
+
+    /// Whether `record` passes the filter. The record is looked at through its serde representation.
+    pub fn matches<T: Record>(&self, record: &T) -> bool {
+        serde_json::to_value(record).is_ok_and(|record| self.matches_value(&record))
+    }
+
+    pub fn matches_value(&self, record: &Value) -> bool {
+        self.expr.eval(record)
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl<T: Record> DocumentEditor<T> {
+    /// The records of the document that pass `filter`, read lazily like `read_records`.
+    pub fn filter(&self, filter: &Filter) -> Result<RecordIter<'static, T>, DocumentError> {
+        let filter = filter.clone();
+        Ok(Box::new(self.read_records()?.filter(move |record| {
+            record
+                .as_ref()
+                .map_or(true, |record| filter.matches(record))
+        })))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Field(Vec<String>),
+    Literal(Value),
+}
+
+impl Operand {
+    fn eval<'a>(&'a self, record: &'a Value) -> &'a Value {
+        match self {
+            Operand::Field(path) => {
+                field_at_names(record, path.iter().map(String::as_str)).unwrap_or(&Value::Null)
+            }
+            Operand::Literal(value) => value,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    /// The conditions of a whole chain, kept flat so a long one does not make a deep tree.
+    Or(Vec<Expr>),
+    And(Vec<Expr>),
+    Not(Box<Expr>),
+    Compare(Operand, Op, Operand),
+    Matches(Operand, Regex),
+    Truthy(Operand),
+}
+
+impl Expr {
+    fn eval(&self, record: &Value) -> bool {
+        match self {
+            Expr::Or(exprs) => exprs.iter().any(|expr| expr.eval(record)),
+            Expr::And(exprs) => exprs.iter().all(|expr| expr.eval(record)),
+            Expr::Not(expr) => !expr.eval(record),
+            Expr::Compare(left, op, right) => compare(left.eval(record), *op, right.eval(record)),
+            Expr::Matches(operand, regex) => {
+                as_text(operand.eval(record)).is_some_and(|text| regex.is_match(&text))
+            }
+            Expr::Truthy(operand) => match operand.eval(record) {
+                Value::Bool(value) => *value,
+                Value::String(text) => text == "true",
+                _ => false,
+            },
+        }
+    }
+}
+
+fn is_null(value: &Value) -> bool {
+    matches!(value, Value::Null) || value.as_str() == Some("")
+}
+
+/// Scalars as text. Lists and tables have none, and null is matched by nothing.
+fn as_text(value: &Value) -> Option<String> {
+    match value {
+        Value::Bool(value) => Some(value.to_string()),
+        Value::Number(value) => Some(value.to_string()),
+        Value::String(value) => Some(value.clone()),
+        Value::Null | Value::Array(_) | Value::Object(_) => None,
+    }
+}
+
+fn as_bool(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(value) => Some(*value),
+        Value::String(text) => text.parse().ok(),
+        _ => None,
+    }
+}
+
+fn order(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::String(left_text), Value::String(right_text)) => {
+            match (as_number(left), as_number(right)) {
+                (Some(left), Some(right)) => left.partial_cmp(&right),
+                _ => Some(left_text.cmp(right_text)),
+            }
+        }
+        (Value::Number(_), _) | (_, Value::Number(_)) => {
+            as_number(left)?.partial_cmp(&as_number(right)?)
+        }
+        (Value::Bool(_), _) | (_, Value::Bool(_)) => Some(as_bool(left)?.cmp(&as_bool(right)?)),
+        _ => None,
+    }
+}
+
+fn compare(left: &Value, op: Op, right: &Value) -> bool {
+    let equal = || match (is_null(left), is_null(right)) {
+        (true, true) => true,
+        (false, false) => left == right || order(left, right) == Some(Ordering::Equal),
+        _ => false,
+    };
+    match op {
+        Op::Eq => equal(),
+        Op::Ne => !equal(),
+        _ if is_null(left) || is_null(right) => false,
+        Op::Lt => order(left, right) == Some(Ordering::Less),
+        Op::Le => matches!(order(left, right), Some(Ordering::Less | Ordering::Equal)),
+        Op::Gt => order(left, right) == Some(Ordering::Greater),
+        Op::Ge => matches!(
+            order(left, right),
+            Some(Ordering::Greater | Ordering::Equal)
+        ),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Name(String),
+    /// A name in backticks, which is a field even when it spells a keyword.
+    Field(String),
+    Text(String),
+    Number(Number),
+    Compare(Op),
+    Match,
+    NotMatch,
+    Dot,
+    Open,
+    Close,
+    End,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Name(name) | Token::Field(name) => write!(f, "`{}`", name),
+            Token::Text(text) => write!(f, "{:?}", text),
+            Token::Number(number) => write!(f, "{}", number),
+            Token::Compare(op) => f.write_str(match op {
+                Op::Eq => "`==`",
+                Op::Ne => "`!=`",
+                Op::Lt => "`<`",
+                Op::Le => "`<=`",
+                Op::Gt => "`>`",
+                Op::Ge => "`>=`",
+            }),
+            Token::Match => f.write_str("`~`"),
+            Token::NotMatch => f.write_str("`!~`"),
+            Token::Dot => f.write_str("`.`"),
+            Token::Open => f.write_str("`(`"),
+            Token::Close => f.write_str("`)`"),
+            Token::End => f.write_str("the end of the expression"),
+        }
+    }
+}
+
+/// A syntax error at byte `offset` of `source`.
+fn syntax_error(source: &str, offset: usize, message: impl Into<String>) -> DocumentError {
+    let location = Location {
+        line: None,
+        column: Some(source[..offset].chars().count() + 1),
+        offset: Some(offset as u64),
+    };
+    DocumentError::parse(source, location, message)
+}
+
+/// Splits the expression into tokens, each with the byte offset it starts at.
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, DocumentError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let mut next_is = |expected: char| chars.next_if(|&(_, c)| c == expected).is_some();
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '.' => Token::Dot,
+            '~' => Token::Match,
+            '=' if next_is('=') => Token::Compare(Op::Eq),
+            '!' if next_is('=') => Token::Compare(Op::Ne),
+            '!' if next_is('~') => Token::NotMatch,
+            '<' if next_is('=') => Token::Compare(Op::Le),
+            '<' => Token::Compare(Op::Lt),
+            '>' if next_is('=') => Token::Compare(Op::Ge),
+            '>' => Token::Compare(Op::Gt),
+            '=' => return Err(syntax_error(source, start, "use `==` to compare")),
+            '!' => return Err(syntax_error(source, start, "use `not` to negate")),
+            '"' | '\'' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, end)) if end == c => break,
+                        Some((at, '\\')) => match chars.next() {
+                            Some((_, 'n')) => text.push('\n'),
+                            Some((_, 't')) => text.push('\t'),
+                            Some((_, escaped @ ('\\' | '"' | '\''))) => text.push(escaped),
+                            _ => return Err(syntax_error(source, at, "unknown escape in text")),
+                        },
+                        Some((_, c)) => text.push(c),
+                        None => return Err(syntax_error(source, start, "unterminated text")),
+                    }
+                }
+                Token::Text(text)
+            }
+            '`' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '`')) => break,
+                        Some((_, c)) => name.push(c),
+                        None => {
+                            return Err(syntax_error(source, start, "unterminated field name"));
+                        }
+                    }
+                }
+                Token::Field(name)
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                let mut end = start + c.len_utf8();
+                while let Some((at, c)) = chars.next_if(|&(_, c)| {
+                    c.is_ascii_alphanumeric() || matches!(c, '.' | '+' | '-' | '_')
+                }) {
+                    end = at + c.len_utf8();
+                }
+                let literal = &source[start..end];
+                let number = literal
+                    .parse::<i64>()
+                    .ok()
+                    .map(Number::from)
+                    .or_else(|| literal.parse::<f64>().ok().and_then(Number::from_f64))
+                    .ok_or_else(|| {
+                        syntax_error(source, start, format!("`{}` is not a number", literal))
+                    })?;
+                Token::Number(number)
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut name = c.to_string();
+                while let Some((_, c)) = chars.next_if(|&(_, c)| c.is_alphanumeric() || c == '_') {
+                    name.push(c);
+                }
+                Token::Name(name)
+            }
+            c => {
+                return Err(syntax_error(
+                    source,
+                    start,
+                    format!("unexpected character `{}`", c),
+                ));
+            }
+        };
+        tokens.push((token, start));
+    }
+    tokens.push((Token::End, source.len()));
+    Ok(tokens)
+}
+
+/// How deep `not` and parentheses can nest, so an expression cannot run the parser
+/// out of stack.
+const MAX_NESTING: usize = 64;
+
+/// A recursive descent parser, one method per precedence level, loosest first.
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+    /// How many `not`s and parentheses the next token is inside.
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.next].0
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens[self.next].1
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.next].0.clone();
+        if token != Token::End {
+            self.next += 1;
+        }
+        token
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Token::Name(name) if name == keyword)
+    }
+
+    fn unexpected(&self, expected: &str) -> DocumentError {
+        syntax_error(
+            self.source,
+            self.offset(),
+            format!("expected {}, found {}", expected, self.peek()),
+        )
+    }
+
+    fn expect_end(&self) -> Result<(), DocumentError> {
+        match self.peek() {
+            Token::End => Ok(()),
+            _ => Err(self.unexpected("`and`, `or` or the end of the expression")),
+        }
+    }
+
+    /// Parses what follows the `not` or `(` at the current token one level deeper.
+    fn nested<R>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<R, DocumentError>,
+    ) -> Result<R, DocumentError> {
+        if self.depth == MAX_NESTING {
+            return Err(syntax_error(
+                self.source,
+                self.offset(),
+                format!("`not` and parentheses nest deeper than {}", MAX_NESTING),
+            ));
+        }
+        self.depth += 1;
+        self.advance();
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn or(&mut self) -> Result<Expr, DocumentError> {
+        let mut exprs = vec![self.and()?];
+        while self.is_keyword("or") {
+            self.advance();
+            exprs.push(self.and()?);
+        }
+        Ok(match exprs.len() {
+            1 => exprs.remove(0),
+            _ => Expr::Or(exprs),
+        })
+    }
+
+    fn and(&mut self) -> Result<Expr, DocumentError> {
+        let mut exprs = vec![self.not()?];
+        while self.is_keyword("and") {
+            self.advance();
+            exprs.push(self.not()?);
+        }
+        Ok(match exprs.len() {
+            1 => exprs.remove(0),
+            _ => Expr::And(exprs),
+        })
+    }
+
+    fn not(&mut self) -> Result<Expr, DocumentError> {
+        if self.is_keyword("not") {
+            return self.nested(|parser| Ok(Expr::Not(Box::new(parser.not()?))));
+        }
+        self.condition()
+    }
+
+    fn condition(&mut self) -> Result<Expr, DocumentError> {
+        if *self.peek() == Token::Open {
+            return self.nested(|parser| {
+                let expr = parser.or()?;
+                if *parser.peek() != Token::Close {
+                    return Err(parser.unexpected("`)`"));
+                }
+                parser.advance();
+                Ok(expr)
+            });
+        }
+        let left = self.operand()?;
+        match self.peek().clone() {
+            Token::Compare(op) => {
+                self.advance();
+                Ok(Expr::Compare(left, op, self.operand()?))
+            }
+            token @ (Token::Match | Token::NotMatch) => {
+                self.advance();
+                let offset = self.offset();
+                let Token::Text(pattern) = self.peek().clone() else {
+                    return Err(self.unexpected("a quoted regex"));
+                };
+                self.advance();
+                let regex = Regex::new(&pattern).map_err(|err| {
+                    // regex draws a picture of the pattern above the message, which only fits a terminal.
+                    let err = err.to_string();
+                    let message = err.lines().last().unwrap_or_default();
+                    let message = message.strip_prefix("error: ").unwrap_or(message);
+                    syntax_error(self.source, offset, format!("invalid regex, {}", message))
+                })?;
+                let expr = Expr::Matches(left, regex);
+                Ok(if token == Token::NotMatch {
+                    Expr::Not(Box::new(expr))
+                } else {
+                    expr
+                })
+            }
+            _ => Ok(Expr::Truthy(left)),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, DocumentError> {
+        let value = match self.peek().clone() {
+            Token::Text(text) => Value::String(text),
+            Token::Number(number) => Value::Number(number),
+            Token::Name(name) => match name.as_str() {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                "null" => Value::Null,
+                "and" | "or" | "not" => return Err(self.unexpected("a field or a value")),
+                _ => return self.field(),
+            },
+            Token::Field(_) => return self.field(),
+            _ => return Err(self.unexpected("a field or a value")),
+        };
+        self.advance();
+        Ok(Operand::Literal(value))
+    }
+
+    fn field(&mut self) -> Result<Operand, DocumentError> {
+        let mut path = Vec::new();
+        loop {
+            match self.peek().clone() {
+                Token::Name(name) | Token::Field(name) => path.push(name),
+                _ => return Err(self.unexpected("a field name")),
+            }
+            self.advance();
+            if *self.peek() != Token::Dot {
+                return Ok(Operand::Field(path));
+            }
+            self.advance();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tokens(source: &str) -> Vec<Token> {
+        tokenize(source)
+            .unwrap()
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect()
+    }
+
+    fn column(err: DocumentError) -> Option<usize> {
+        match err {
+            DocumentError::Parse { location, .. } => location.column,
+            _ => panic!("expected a parse error, got {:?}", err),
+        }
+    }
+
+    fn matches(expression: &str, record: Value) -> bool {
+        Filter::parse(expression).unwrap().matches_value(&record)
+    }
+
+    #[test]
+    fn tokenizes_operators_literals_and_names() {
+        assert_eq!(
+            tokens(r#"a.b >= -1.5 and `first name` !~ 'x\'y' or(c != "")"#),
+            [
+                Token::Name("a".into()),
+                Token::Dot,
+                Token::Name("b".into()),
+                Token::Compare(Op::Ge),
+                Token::Number(Number::from_f64(-1.5).unwrap()),
+                Token::Name("and".into()),
+                Token::Field("first name".into()),
+                Token::NotMatch,
+                Token::Text("x'y".into()),
+                Token::Name("or".into()),
+                Token::Open,
+                Token::Name("c".into()),
+                Token::Compare(Op::Ne),
+                Token::Text("".into()),
+                Token::Close,
+                Token::End,
+            ]
+        );
+        assert_eq!(tokens("12"), [Token::Number(12.into()), Token::End]);
+    }
+
+    #[test]
+    fn tokenizer_errors_point_at_the_character() {
+        assert_eq!(column(tokenize("age = 3").unwrap_err()), Some(5));
+        assert_eq!(column(tokenize("!age").unwrap_err()), Some(1));
+        assert_eq!(column(tokenize("name == 'open").unwrap_err()), Some(9));
+        assert_eq!(column(tokenize("x == 1x").unwrap_err()), Some(6));
+        assert_eq!(column(tokenize("é == #").unwrap_err()), Some(6));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let record = json!({"a": true, "b": false, "c": false});
+        assert!(matches("a or b and c", record.clone()));
+        assert!(!matches("(a or b) and c", record.clone()));
+        assert!(matches("not b and not c", record.clone()));
+        assert!(!matches("not (a or b)", record));
+    }
+
+    #[test]
+    fn parse_errors_point_at_the_token() {
+        assert_eq!(column(Filter::parse("age >").unwrap_err()), Some(6));
+        assert_eq!(column(Filter::parse("(a or b").unwrap_err()), Some(8));
+        assert_eq!(column(Filter::parse("a b").unwrap_err()), Some(3));
+        assert_eq!(column(Filter::parse("name ~ other").unwrap_err()), Some(8));
+        assert_eq!(column(Filter::parse("name ~ '('").unwrap_err()), Some(8));
+        assert_eq!(column(Filter::parse("and == 1").unwrap_err()), Some(1));
+    }
+
+    #[test]
+    fn deep_nesting_is_a_parse_error_instead_of_a_stack_overflow() {
+        let nots = format!("{}a", "not ".repeat(30_000));
+        assert_eq!(
+            column(Filter::parse(&nots).unwrap_err()),
+            Some(MAX_NESTING * 4 + 1)
+        );
+        let parentheses = format!("{}a{}", "(".repeat(30_000), ")".repeat(30_000));
+        assert_eq!(
+            column(Filter::parse(&parentheses).unwrap_err()),
+            Some(MAX_NESTING + 1)
+        );
+        let allowed = format!("{}a", "not ".repeat(MAX_NESTING));
+        assert!(matches(&allowed, json!({"a": true})));
+        let chain = vec!["a"; 30_000].join(" and ");
+        assert!(matches(&chain, json!({"a": true})));
+    }
+
+    #[test]
+    fn numbers_in_text_compare_as_numbers() {
+        let record = json!({"age": "10", "zip": "00501", "name": "bob"});
+        assert!(matches("age > 9", record.clone()));
+        assert!(matches("age > '9'", record.clone()));
+        assert!(matches("age == 10.0", record.clone()));
+        assert!(matches("zip == 501", record.clone()));
+        assert!(matches("name > 'alice'", record.clone()));
+        assert!(!matches("name > 1", record.clone()));
+        assert!(matches("'inf' > 'banana'", record));
+    }
+
+    #[test]
+    fn fields_follow_dotted_paths_and_default_to_null() {
+        let record = json!({"address": {"zip": "12345"}, "email": "", "active": "true"});
+        assert!(matches("address.zip ~ '^12'", record.clone()));
+        assert!(matches("`address`.`zip` == 12345", record.clone()));
+        assert!(matches("missing == null", record.clone()));
+        assert!(matches("email == null", record.clone()));
+        assert!(!matches("missing < 1", record.clone()));
+        assert!(!matches("address ~ '.'", record.clone()));
+        assert!(matches("active", record.clone()));
+        assert!(!matches("address", record));
+    }
+
+    #[test]
+    fn not_match_negates_the_regex() {
+        let record = json!({"name": "obvious", "age": 25});
+        assert!(matches("name !~ '^x'", record.clone()));
+        assert!(!matches("name !~ '^obv'", record.clone()));
+        assert!(matches("age ~ '^2'", record));
+    }
+}
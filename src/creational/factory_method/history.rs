@@ -0,0 +1,311 @@
+use std::collections::VecDeque;
+
+/// How many edits an editor can undo unless it is told otherwise.
+pub(super) const DEFAULT_DEPTH: usize = 100;
+
+/// One edit of the records held in memory.
+pub(super) enum Change<T> {
+    Insert { index: usize, record: T },
+    Remove { index: usize },
+    Replace { index: usize, record: T },
+}
+
+impl<T> Change<T> {
+    /// Applies the change to `records`, whose index has been checked by then,
+    /// and returns the change that reverts it.
+    fn apply(self, records: &mut Vec<T>) -> Change<T> {
+        match self {
+            Change::Insert { index, record } => {
+                records.insert(index, record);
+                Change::Remove { index }
+            }
+            Change::Remove { index } => Change::Insert {
+                index,
+                record: records.remove(index),
+            },
+            Change::Replace { index, record } => Change::Replace {
+                index,
+                record: std::mem::replace(&mut records[index], record),
+            },
+        }
+    }
+}
+
+/// What a single undo or redo does, which is all edits of a transaction at once.
+struct Step<T> {
+    /// Applied last to first, so the changes of a transaction are reverted in reverse order.
+    changes: Vec<Change<T>>,
+    /// The version of the records once the step is applied.
+    version: u64,
+}
+
+impl<T> Step<T> {
+    /// Applies the step and returns the one that goes back to `current`. Its changes come
+    /// out in the order they were applied, so it applies them in reverse again.
+    fn apply(self, records: &mut Vec<T>, current: u64) -> Step<T> {
+        let changes = self
+            .changes
+            .into_iter()
+            .rev()
+            .map(|change| change.apply(records))
+            .collect();
+        Step {
+            changes,
+            version: current,
+        }
+    }
+}
+
+/// Where a transaction started, so it can be rolled back to there.
+pub(super) struct Mark {
+    outermost: bool,
+    changes: usize,
+    version: u64,
+}
+
+/// The undo and redo stacks of an editor. Every state of the records gets a version,
+/// so the editor is dirty exactly when the version differs from the one last saved,
+/// also after undoing back to it.
+pub(super) struct History<T> {
+    depth: usize,
+    undo: VecDeque<Step<T>>,
+    redo: Vec<Step<T>>,
+    /// The transaction being recorded.
+    open: Option<Step<T>>,
+    version: u64,
+    last_version: u64,
+    saved_version: u64,
+}
+
+impl<T> History<T> {
+    pub(super) fn new(depth: usize) -> Self {
+        History {
+            depth,
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            open: None,
+            version: 0,
+            last_version: 0,
+            saved_version: 0,
+        }
+    }
+
+    pub(super) fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+        self.undo.drain(..self.undo.len().saturating_sub(depth));
+    }
+
+    /// Forgets every edit, for records that were just read from the document.
+    pub(super) fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.open = None;
+        self.saved_version = self.version;
+    }
+
+    pub(super) fn mark_saved(&mut self) {
+        self.saved_version = self.version;
+    }
+
+    pub(super) fn is_dirty(&self) -> bool {
+        self.version != self.saved_version
+    }
+
+    pub(super) fn can_undo(&self) -> bool {
+        self.open.is_none() && !self.undo.is_empty()
+    }
+
+    pub(super) fn can_redo(&self) -> bool {
+        self.open.is_none() && !self.redo.is_empty()
+    }
+
+    /// Applies an edit and remembers how to revert it. Outside a transaction this
+    /// drops the redo branch, inside one that waits until the transaction is committed.
+    pub(super) fn apply(&mut self, records: &mut Vec<T>, change: Change<T>) {
+        let revert = change.apply(records);
+        let before = self.version;
+        self.last_version += 1;
+        self.version = self.last_version;
+        match &mut self.open {
+            Some(transaction) => transaction.changes.push(revert),
+            None => {
+                self.redo.clear();
+                self.push_undo(Step {
+                    changes: vec![revert],
+                    version: before,
+                });
+            }
+        }
+    }
+
+    pub(super) fn undo(&mut self, records: &mut Vec<T>) -> bool {
+        if self.open.is_some() {
+            return false;
+        }
+        let Some(step) = self.undo.pop_back() else {
+            return false;
+        };
+        let version = step.version;
+        self.redo.push(step.apply(records, self.version));
+        self.version = version;
+        true
+    }
+
+    pub(super) fn redo(&mut self, records: &mut Vec<T>) -> bool {
+        if self.open.is_some() {
+            return false;
+        }
+        let Some(step) = self.redo.pop() else {
+            return false;
+        };
+        let version = step.version;
+        let back = step.apply(records, self.version);
+        self.push_undo(back);
+        self.version = version;
+        true
+    }
+
+    /// Starts a transaction, or a nested one that becomes part of the one already open.
+    pub(super) fn begin(&mut self) -> Mark {
+        let outermost = self.open.is_none();
+        let transaction = self.open.get_or_insert_with(|| Step {
+            changes: Vec::new(),
+            version: self.version,
+        });
+        Mark {
+            outermost,
+            changes: transaction.changes.len(),
+            version: self.version,
+        }
+    }
+
+    /// Ends a transaction. The outermost one becomes a single undo step.
+    pub(super) fn commit(&mut self, mark: Mark) {
+        if !mark.outermost {
+            return;
+        }
+        if let Some(transaction) = self.open.take()
+            && !transaction.changes.is_empty()
+        {
+            self.redo.clear();
+            self.push_undo(transaction);
+        }
+    }
+
+    /// Reverts the edits made since `mark`, and ends the transaction if it is the outermost.
+    pub(super) fn rollback(&mut self, records: &mut Vec<T>, mark: Mark) {
+        if let Some(transaction) = &mut self.open {
+            for change in transaction.changes.drain(mark.changes..).rev() {
+                change.apply(records);
+            }
+            self.version = mark.version;
+        }
+        if mark.outermost {
+            self.open = None;
+        }
+    }
+
+    fn push_undo(&mut self, step: Step<T>) {
+        self.undo.push_back(step);
+        self.undo
+            .drain(..self.undo.len().saturating_sub(self.depth));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(index: usize, record: &'static str) -> Change<&'static str> {
+        Change::Insert { index, record }
+    }
+
+    #[test]
+    fn undo_back_to_the_saved_state_is_clean() {
+        let mut records = vec!["a"];
+        let mut history = History::new(DEFAULT_DEPTH);
+        history.apply(&mut records, insert(1, "b"));
+        history.mark_saved();
+        history.apply(&mut records, insert(2, "c"));
+        history.apply(&mut records, Change::Remove { index: 0 });
+        assert!(history.is_dirty());
+
+        assert!(history.undo(&mut records));
+        assert!(history.is_dirty());
+        assert!(history.undo(&mut records));
+        assert_eq!(records, ["a", "b"]);
+        assert!(!history.is_dirty());
+
+        // Undoing past the save is an unsaved change again.
+        assert!(history.undo(&mut records));
+        assert_eq!(records, ["a"]);
+        assert!(history.is_dirty());
+        assert!(history.redo(&mut records));
+        assert!(!history.is_dirty());
+    }
+
+    #[test]
+    fn a_new_edit_drops_the_redo_branch() {
+        let mut records = vec!["a"];
+        let mut history = History::new(DEFAULT_DEPTH);
+        history.apply(&mut records, insert(1, "b"));
+        history.apply(&mut records, insert(2, "c"));
+        assert!(history.undo(&mut records));
+        assert!(history.can_redo());
+
+        history.apply(
+            &mut records,
+            Change::Replace {
+                index: 0,
+                record: "z",
+            },
+        );
+        assert!(!history.can_redo());
+        assert!(!history.redo(&mut records));
+        assert_eq!(records, ["z", "b"]);
+
+        assert!(history.undo(&mut records));
+        assert!(history.undo(&mut records));
+        assert_eq!(records, ["a"]);
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn rolling_back_a_nested_transaction_keeps_the_outer_edits() {
+        let mut records = vec!["a"];
+        let mut history = History::new(DEFAULT_DEPTH);
+        let outer = history.begin();
+        history.apply(&mut records, insert(1, "b"));
+        let inner = history.begin();
+        history.apply(&mut records, insert(2, "c"));
+        history.apply(&mut records, Change::Remove { index: 0 });
+        assert!(!history.can_undo());
+
+        history.rollback(&mut records, inner);
+        assert_eq!(records, ["a", "b"]);
+        history.commit(outer);
+        assert!(history.is_dirty());
+
+        // The outer transaction is a single step, without the rolled back edits.
+        assert!(history.undo(&mut records));
+        assert_eq!(records, ["a"]);
+        assert!(!history.is_dirty());
+        assert!(!history.can_undo());
+        assert!(history.redo(&mut records));
+        assert_eq!(records, ["a", "b"]);
+    }
+
+    #[test]
+    fn rolling_back_the_outermost_transaction_restores_the_version() {
+        let mut records = vec!["a"];
+        let mut history = History::new(DEFAULT_DEPTH);
+        let outer = history.begin();
+        let inner = history.begin();
+        history.apply(&mut records, insert(1, "b"));
+        history.commit(inner);
+        history.rollback(&mut records, outer);
+        assert_eq!(records, ["a"]);
+        assert!(!history.is_dirty());
+        assert!(!history.can_undo());
+    }
+}
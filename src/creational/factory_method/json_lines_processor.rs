@@ -0,0 +1,136 @@
+use super::{
+    DocumentError, DocumentProcessor, Location, Record, RecordIter, RecordWriter,
+    json_processor::{TextPosition, json_error},
+};
+use std::{
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    marker::PhantomData,
+};
+
+/// Newline delimited json: one record per line, blank lines are skipped.
+pub struct JsonLinesProcessor {}
+
+impl<T: Record> DocumentProcessor<T> for JsonLinesProcessor {
+    fn read_from<'a>(
+        &self,
+        source: Box<dyn Read + 'a>,
+        name: &str,
+    ) -> Result<RecordIter<'a, T>, DocumentError> {
+        Ok(Box::new(JsonLines {
+            reader: BufReader::new(source),
+            file_name: name.to_string(),
+            line: Vec::new(),
+            line_number: 0,
+            done: false,
+            record_type: PhantomData,
+        }))
+    }
+
+    fn writer_to(
+        &self,
+        sink: Box<dyn Write>,
+        name: &str,
+    ) -> Result<Box<dyn RecordWriter<T>>, DocumentError> {
+        Ok(Box::new(JsonLinesRecordWriter {
+            file_name: name.to_string(),
+            out: BufWriter::new(sink),
+        }))
+    }
+}
+
+struct JsonLines<'a, T> {
+    file_name: String,
+    reader: BufReader<Box<dyn Read + 'a>>,
+    line: Vec<u8>,
+    line_number: usize,
+    /// Set once reading failed, since the source cannot go on from a failed read.
+    done: bool,
+    record_type: PhantomData<T>,
+}
+
+impl<T: Record> Iterator for JsonLines<'_, T> {
+    type Item = Result<T, DocumentError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        loop {
+            self.line.clear();
+            match self.reader.read_until(b'\n', &mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => self.line_number += 1,
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(DocumentError::io(&self.file_name, err)));
+                }
+            }
+            if self.line.trim_ascii().is_empty() {
+                continue;
+            }
+            // serde_json only sees the one line, so its "line 1" is moved to the real line.
+            return Some(serde_json::from_slice(&self.line).map_err(|err| {
+                json_error(&self.file_name, err, TextPosition::line(self.line_number))
+            }));
+        }
+    }
+}
+
+struct JsonLinesRecordWriter {
+    file_name: String,
+    out: BufWriter<Box<dyn Write>>,
+}
+
+impl<T: Record> RecordWriter<T> for JsonLinesRecordWriter {
+    fn write_record(&mut self, record: &T) -> Result<(), DocumentError> {
+        // Serialize first, so a record that cannot be written leaves no half line behind.
+        let mut line = serde_json::to_vec(record).map_err(|err| {
+            DocumentError::schema_mismatch(&self.file_name, Location::default(), err.to_string())
+        })?;
+        line.push(b'\n');
+        self.out
+            .write_all(&line)
+            .map_err(|err| DocumentError::io(&self.file_name, err))
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), DocumentError> {
+        let file_name = self.file_name;
+        self.out
+            .flush()
+            .map_err(|err| DocumentError::io(&file_name, err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creational::factory_method::DynamicRecord;
+    use std::io;
+
+    /// A source that hands out one line and then fails on every read.
+    struct Broken(&'static [u8]);
+
+    impl Read for Broken {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(io::Error::other("the disk went away"));
+            }
+            let len = buf.len().min(self.0.len());
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn a_failed_read_ends_the_records() {
+        let records: Vec<Result<DynamicRecord, _>> = JsonLinesProcessor {}
+            .read_from(Box::new(Broken(b"{\"a\":1}\n")), "broken.jsonl")
+            .unwrap()
+            .take(10)
+            .collect();
+        assert_eq!(records.len(), 2);
+        assert!(records[0].is_ok());
+        assert!(matches!(records[1], Err(DocumentError::Io { .. })));
+    }
+}
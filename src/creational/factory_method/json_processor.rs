@@ -0,0 +1,489 @@
+use super::{
+    DocumentError, DocumentProcessor, Location, Record, RecordIter, RecordWriter,
+    error::strip_position,
+};
+use serde_json::{Serializer, error::Category, ser::PrettyFormatter};
+use std::{
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    marker::PhantomData,
+};
+
+/// A json document holds a single object or an array of objects. Arrays are read one
+/// element at a time, so a large one is never held in memory as a whole.
+pub struct JsonProcessor {}
+
+impl<T: Record> DocumentProcessor<T> for JsonProcessor {
+    fn read_from<'a>(
+        &self,
+        source: Box<dyn Read + 'a>,
+        name: &str,
+    ) -> Result<RecordIter<'a, T>, DocumentError> {
+        let file_name = name.to_string();
+        let io_error = |err| DocumentError::io(&file_name, err);
+        let mut reader = BufReader::new(source);
+        let mut position = TextPosition::start();
+        skip_whitespace(&mut reader, &mut position).map_err(io_error)?;
+        if reader.fill_buf().map_err(io_error)?.first() == Some(&b'[') {
+            reader.consume(1);
+            position.advance(b"[");
+            return Ok(Box::new(JsonArrayRecords::new(file_name, reader, position)));
+        }
+        // Not an array: the document is a single object, or several concatenated ones.
+        Ok(Box::new(
+            serde_json::Deserializer::from_reader(reader)
+                .into_iter::<T>()
+                .map(move |result| result.map_err(|err| json_error(&file_name, err, position))),
+        ))
+    }
+
+    fn writer_to(
+        &self,
+        sink: Box<dyn Write>,
+        name: &str,
+    ) -> Result<Box<dyn RecordWriter<T>>, DocumentError> {
+        Ok(Box::new(JsonRecordWriter::new(
+            sink,
+            name,
+            JsonLayout::default(),
+        )))
+    }
+
+    fn writer_like(
+        &self,
+        sink: Box<dyn Write>,
+        name: &str,
+        original: &[u8],
+    ) -> Result<Box<dyn RecordWriter<T>>, DocumentError> {
+        Ok(Box::new(JsonRecordWriter::new(
+            sink,
+            name,
+            JsonLayout::of(original),
+        )))
+    }
+}
+
+/// How a json document is laid out: indented by `indent` or all on one line, and
+/// whether a lone record is still written as an array.
+struct JsonLayout {
+    indent: Option<Vec<u8>>,
+    array: bool,
+}
+
+impl Default for JsonLayout {
+    fn default() -> Self {
+        JsonLayout {
+            indent: Some(b"  ".to_vec()),
+            array: false,
+        }
+    }
+}
+
+impl JsonLayout {
+    /// The layout of the document starting with `head`. The indentation is that of the
+    /// first indented line, and a document on a single line is written on one again.
+    fn of(head: &[u8]) -> Self {
+        let head = head.trim_ascii();
+        let indent = match head.contains(&b'\n') {
+            false => None,
+            true => head
+                .split(|&b| b == b'\n')
+                .skip(1)
+                .find(|line| !line.trim_ascii().is_empty())
+                .map(|line| {
+                    let len = line
+                        .iter()
+                        .take_while(|&&b| b == b' ' || b == b'\t')
+                        .count();
+                    line[..len].to_vec()
+                }),
+        };
+        JsonLayout {
+            indent,
+            array: head.first() == Some(&b'['),
+        }
+    }
+
+    fn serialize<T: Record>(&self, record: &T) -> serde_json::Result<Vec<u8>> {
+        match &self.indent {
+            Some(indent) => {
+                let mut element = Vec::new();
+                let formatter = PrettyFormatter::with_indent(indent);
+                record.serialize(&mut Serializer::with_formatter(&mut element, formatter))?;
+                Ok(element)
+            }
+            None => serde_json::to_vec(record),
+        }
+    }
+
+    /// What goes before the element at `index` of an array.
+    fn separator(&self, index: usize) -> Vec<u8> {
+        let opening: &[u8] = if index == 0 { b"[" } else { b"," };
+        match &self.indent {
+            Some(indent) => [opening, b"\n", indent].concat(),
+            None => opening.to_vec(),
+        }
+    }
+
+    fn closing(&self) -> &'static [u8] {
+        match self.indent {
+            Some(_) => b"\n]\n",
+            None => b"]\n",
+        }
+    }
+}
+
+/// Writes the same two shapes `JsonProcessor` reads: a lone record becomes a single object,
+/// anything else becomes an array. A document that was an array stays one, even when it is
+/// left with a single record. Otherwise the first record is held back until the second one
+/// shows up, because only then is it known which shape to open with.
+struct JsonRecordWriter {
+    file_name: String,
+    out: BufWriter<Box<dyn Write>>,
+    layout: JsonLayout,
+    first: Option<Vec<u8>>,
+    count: usize,
+}
+
+impl JsonRecordWriter {
+    fn new(sink: Box<dyn Write>, name: &str, layout: JsonLayout) -> Self {
+        JsonRecordWriter {
+            file_name: name.to_string(),
+            out: BufWriter::new(sink),
+            layout,
+            first: None,
+            count: 0,
+        }
+    }
+
+    fn write_element(&mut self, index: usize, element: &[u8]) -> io::Result<()> {
+        self.out.write_all(&self.layout.separator(index))?;
+        // Pretty printed json never has a raw newline inside a string, so this only re-indents.
+        for (i, line) in element.split(|&b| b == b'\n').enumerate() {
+            if i > 0 {
+                self.out.write_all(b"\n")?;
+                self.out
+                    .write_all(self.layout.indent.as_deref().unwrap_or_default())?;
+            }
+            self.out.write_all(line)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Record> RecordWriter<T> for JsonRecordWriter {
+    fn write_record(&mut self, record: &T) -> Result<(), DocumentError> {
+        let element = self.layout.serialize(record).map_err(|err| {
+            DocumentError::schema_mismatch(&self.file_name, Location::default(), err.to_string())
+        })?;
+        self.count += 1;
+        let written = match self.count {
+            1 if !self.layout.array => {
+                self.first = Some(element);
+                Ok(())
+            }
+            2 if self.first.is_some() => {
+                let first = self.first.take().unwrap_or_default();
+                self.write_element(0, &first)
+                    .and_then(|()| self.write_element(1, &element))
+            }
+            count => self.write_element(count - 1, &element),
+        };
+        written.map_err(|err| DocumentError::io(&self.file_name, err))
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), DocumentError> {
+        let closing = match self.first.take() {
+            Some(first) => [first.as_slice(), b"\n"].concat(),
+            None if self.count == 0 => b"[]\n".to_vec(),
+            None => self.layout.closing().to_vec(),
+        };
+        let file_name = self.file_name;
+        let io_error = |err| DocumentError::io(&file_name, err);
+        self.out.write_all(&closing).map_err(io_error)?;
+        self.out.flush().map_err(io_error)
+    }
+}
+
+/// Line and column of the next unread byte. serde_json only ever sees one element,
+/// so its positions are moved by where the element starts in the file.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct TextPosition {
+    line: usize,
+    /// Bytes read on the current line so far.
+    column: usize,
+}
+
+impl TextPosition {
+    pub(super) fn start() -> Self {
+        TextPosition { line: 1, column: 0 }
+    }
+
+    /// The start of line `line`.
+    pub(super) fn line(line: usize) -> Self {
+        TextPosition { line, column: 0 }
+    }
+
+    fn advance(&mut self, bytes: &[u8]) {
+        match bytes.iter().rposition(|&b| b == b'\n') {
+            Some(last) => {
+                self.line += bytes.iter().filter(|&&b| b == b'\n').count();
+                self.column = bytes.len() - last - 1;
+            }
+            None => self.column += bytes.len(),
+        }
+    }
+}
+
+/// Sorts a serde_json error into the matching `DocumentError`. `start` is where the
+/// text serde_json was given begins in the file.
+pub(super) fn json_error(path: &str, err: serde_json::Error, start: TextPosition) -> DocumentError {
+    let location = Location {
+        line: Some(start.line + err.line().saturating_sub(1)),
+        column: Some(if err.line() <= 1 {
+            start.column + err.column()
+        } else {
+            err.column()
+        }),
+        offset: None,
+    };
+    match err.classify() {
+        Category::Io => DocumentError::io(path, err.into()),
+        Category::Data => {
+            DocumentError::schema_mismatch(path, location, strip_position(&err.to_string()))
+        }
+        Category::Syntax | Category::Eof => {
+            DocumentError::parse(path, location, strip_position(&err.to_string()))
+        }
+    }
+}
+
+fn skip_whitespace<R: BufRead>(reader: &mut R, position: &mut TextPosition) -> io::Result<()> {
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(());
+        }
+        let skipped = buf.iter().take_while(|b| b.is_ascii_whitespace()).count();
+        let reached_data = skipped < buf.len();
+        position.advance(&buf[..skipped]);
+        reader.consume(skipped);
+        if reached_data {
+            return Ok(());
+        }
+    }
+}
+
+/// Walks the elements of a top level json array without loading the whole array.
+/// Each element is cut out as raw bytes and handed to serde_json on its own,
+/// so memory use is bounded by the largest single element.
+struct JsonArrayRecords<'a, T> {
+    file_name: String,
+    reader: BufReader<Box<dyn Read + 'a>>,
+    element: Vec<u8>,
+    /// Where the unread part of the file starts.
+    position: TextPosition,
+    /// Where `element` starts.
+    element_start: TextPosition,
+    done: bool,
+    record_type: PhantomData<T>,
+}
+
+impl<'a, T: Record> JsonArrayRecords<'a, T> {
+    fn new(
+        file_name: String,
+        reader: BufReader<Box<dyn Read + 'a>>,
+        position: TextPosition,
+    ) -> Self {
+        JsonArrayRecords {
+            file_name,
+            reader,
+            element: Vec::new(),
+            position,
+            element_start: position,
+            done: false,
+            record_type: PhantomData,
+        }
+    }
+
+    /// Fills `self.element` with the next element. Returns false once the closing `]` is reached.
+    fn read_element(&mut self) -> Result<bool, DocumentError> {
+        let io_error = |err| DocumentError::io(&self.file_name, err);
+        self.element.clear();
+        skip_whitespace(&mut self.reader, &mut self.position).map_err(io_error)?;
+        self.element_start = self.position;
+        let (mut depth, mut in_string, mut escaped) = (0usize, false, false);
+        loop {
+            let buf = self.reader.fill_buf().map_err(io_error)?;
+            if buf.is_empty() {
+                let location = Location {
+                    line: Some(self.position.line),
+                    column: Some(self.position.column),
+                    offset: None,
+                };
+                return Err(DocumentError::parse(
+                    &self.file_name,
+                    location,
+                    "unexpected end of json array",
+                ));
+            }
+            let mut terminator = None;
+            for (i, &byte) in buf.iter().enumerate() {
+                if in_string {
+                    match byte {
+                        _ if escaped => escaped = false,
+                        b'\\' => escaped = true,
+                        b'"' => in_string = false,
+                        _ => {}
+                    }
+                    continue;
+                }
+                match byte {
+                    b'"' => in_string = true,
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' if depth > 0 => depth -= 1,
+                    b',' | b']' if depth == 0 => {
+                        terminator = Some((i, byte));
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            match terminator {
+                Some((i, byte)) => {
+                    self.element.extend_from_slice(&buf[..i]);
+                    self.position.advance(&buf[..=i]);
+                    self.reader.consume(i + 1);
+                    if byte == b']' {
+                        self.done = true;
+                        // `[]` and a trailing `]` after the last element carry no record.
+                        return Ok(!self.element.trim_ascii().is_empty());
+                    }
+                    return Ok(true);
+                }
+                None => {
+                    let len = buf.len();
+                    self.element.extend_from_slice(buf);
+                    self.position.advance(buf);
+                    self.reader.consume(len);
+                }
+            }
+        }
+    }
+}
+
+impl<T: Record> Iterator for JsonArrayRecords<'_, T> {
+    type Item = Result<T, DocumentError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.read_element() {
+            Ok(true) => Some(
+                serde_json::from_slice(&self.element)
+                    .map_err(|err| json_error(&self.file_name, err, self.element_start)),
+            ),
+            Ok(false) => None,
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    /// The records of `json`, read through a buffer of `capacity` bytes.
+    fn elements(json: &'static str, capacity: usize) -> Vec<Result<Value, DocumentError>> {
+        let source: Box<dyn Read> = Box::new(json.as_bytes());
+        let mut reader = BufReader::with_capacity(capacity, source);
+        let mut position = TextPosition::start();
+        skip_whitespace(&mut reader, &mut position).unwrap();
+        assert_eq!(reader.fill_buf().unwrap().first(), Some(&b'['));
+        reader.consume(1);
+        position.advance(b"[");
+        JsonArrayRecords::new("test.json".to_string(), reader, position).collect()
+    }
+
+    fn values(json: &'static str, capacity: usize) -> Vec<Value> {
+        elements(json, capacity)
+            .into_iter()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn elements_can_span_buffer_refills() {
+        let json = r#"[{"name": "ann", "tags": [1, 2]}, {"name": "bob"}, 3]"#;
+        let expected = vec![
+            json!({"name": "ann", "tags": [1, 2]}),
+            json!({"name": "bob"}),
+            json!(3),
+        ];
+        for capacity in [1, 2, 3, 7, 64] {
+            assert_eq!(values(json, capacity), expected, "capacity {}", capacity);
+        }
+    }
+
+    #[test]
+    fn brackets_commas_and_escapes_inside_strings_are_text() {
+        let json = r#"["a,b", "c]d", {"e": "[,]}"}, "say \"hi\", ]", "back\\", "x"]"#;
+        let expected = vec![
+            json!("a,b"),
+            json!("c]d"),
+            json!({"e": "[,]}"}),
+            json!("say \"hi\", ]"),
+            json!("back\\"),
+            json!("x"),
+        ];
+        for capacity in [1, 4, 64] {
+            assert_eq!(values(json, capacity), expected, "capacity {}", capacity);
+        }
+    }
+
+    #[test]
+    fn the_closing_bracket_carries_no_record() {
+        assert_eq!(values("[]", 64), Vec::<Value>::new());
+        assert_eq!(values("[ \n ]\n", 64), Vec::<Value>::new());
+        assert_eq!(values("[1, 2 ]\n", 64), vec![json!(1), json!(2)]);
+        assert_eq!(values("[\n  1,\n  2\n]", 1), vec![json!(1), json!(2)]);
+    }
+
+    #[test]
+    fn an_unterminated_array_fails_once() {
+        for json in ["[1, 2", r#"[1, {"a": "]"#, "["] {
+            let results = elements(json, 64);
+            let error = results.last().unwrap().as_ref().unwrap_err();
+            assert!(
+                matches!(error, DocumentError::Parse { message, .. } if message == "unexpected end of json array"),
+                "{}: {:?}",
+                json,
+                error
+            );
+            assert!(
+                results[..results.len() - 1].iter().all(Result::is_ok),
+                "{}",
+                json
+            );
+        }
+    }
+
+    #[test]
+    fn errors_point_into_the_file_past_earlier_elements() {
+        let json = "[\n  {\"a\": 1},\n  {\"a\": tru},\n  {\"a\":\n    nul}\n]";
+        let results = elements(json, 4);
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap(), &json!({"a": 1}));
+        let location = |result: &Result<Value, DocumentError>| {
+            let location = result.as_ref().unwrap_err().location().unwrap();
+            (location.line, location.column)
+        };
+        // `tru` is cut short by the `}` at column 12 of line 3.
+        assert_eq!(location(&results[1]), (Some(3), Some(12)));
+        assert_eq!(location(&results[2]), (Some(5), Some(8)));
+    }
+}
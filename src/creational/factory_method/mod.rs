@@ -0,0 +1,908 @@
+/*
+   Problem statement: Document editor application. Lets keep it minimal.
+       We will read and parse the data and store it in a struct. After that we will print the struct value as json.
+       Support csv, json, json lines, yaml, toml and xml.
+*/
+
+mod aggregate;
+mod async_processor;
+mod batch;
+mod cache;
+mod compression;
+mod convert;
+mod csv_processor;
+mod diff;
+mod dynamic_record;
+mod editing;
+mod encoding;
+mod error;
+mod filter;
+mod history;
+mod json_lines_processor;
+mod json_processor;
+mod registry;
+#[cfg(test)]
+mod test_files;
+mod text_value;
+mod toml_processor;
+mod validation;
+mod watch;
+mod xml_processor;
+mod yaml_processor;
+
+pub use aggregate::{Aggregate, AggregateReport, Aggregation, GroupBy, GroupResult};
+pub use async_processor::{AsyncDocumentEditor, AsyncDocumentProcessor, BoxFuture};
+pub use batch::{BatchReport, BatchSummary, FileResult, process_batch};
+pub use cache::{CacheStats, DocumentCache};
+pub use compression::Compression;
+pub use convert::{ConvertReport, RecordFailure, convert};
+pub use csv_processor::{CsvDialect, CsvProcessor};
+pub use diff::{ChangedRecord, DocumentDiff, FieldChange, UnmatchedRecord, diff};
+pub use dynamic_record::DynamicRecord;
+pub use editing::RecordRef;
+pub use encoding::Encoding;
+pub use error::{DocumentError, Location};
+pub use filter::Filter;
+pub use json_lines_processor::JsonLinesProcessor;
+pub use json_processor::JsonProcessor;
+pub use registry::{ProcessorConstructor, ProcessorRegistry};
+pub use toml_processor::TomlProcessor;
+pub use validation::{DerivedRules, RecordValidator, Rule, RuleSet, Violation};
+pub use watch::{DEFAULT_DEBOUNCE, DEFAULT_POLL_INTERVAL, Subscription, Watch};
+pub use xml_processor::XmlProcessor;
+pub use yaml_processor::YamlProcessor;
+
+use history::History;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use std::{
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::Path,
+    process,
+    rc::Rc,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+use validator::Validate;
+
+#[derive(Serialize, Deserialize, Validate, Debug)]
+struct DocData {
+    #[validate(length(min = 1, message = "must not be empty"))]
+    name: String,
+    #[validate(range(max = 150, message = "must be at most 150"))]
+    age: u16,
+}
+
+/// Anything serde can read and write can be loaded through a `DocumentProcessor`.
+pub trait Record: DeserializeOwned + Serialize + 'static {}
+
+impl<T: DeserializeOwned + Serialize + 'static> Record for T {}
+
+/// Lazily parsed records, one item per record in the document, so a large document
+/// never has to fit in memory. `'a` is how long the source they are read from lives,
+/// `'static` unless it is borrowed.
+pub type RecordIter<'a, T> = Box<dyn Iterator<Item = Result<T, DocumentError>> + 'a>;
+
+/// Receives records one at a time and writes them out in the processor's format.
+pub trait RecordWriter<T: Record> {
+    fn write_record(&mut self, record: &T) -> Result<(), DocumentError>;
+
+    /// Closes the document and flushes the sink. Documents written to a file through
+    /// `DocumentProcessor::writer` are flushed all the way to disk.
+    fn finish(self: Box<Self>) -> Result<(), DocumentError>;
+}
+
+/// A file whose `flush` also syncs it to disk. Writers only see a `Write`, so this is
+/// how flushing at the end of a document makes the file durable.
+struct DurableFile(File);
+
+impl Write for DurableFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.sync_all()
+    }
+}
+
+/// The formats there are built-in processors for. A file's type can be taken from its
+/// extension or, failing that, sniffed from its first bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentType {
+    Json,
+    JsonLines,
+    Csv,
+    Yaml,
+    Toml,
+    Xml,
+}
+
+/// How much of a file is looked at when its format has to be sniffed.
+const SNIFF_LEN: usize = 8 * 1024;
+
+impl DocumentType {
+    /// The name the built-in processor for this type is registered under.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DocumentType::Json => "json",
+            DocumentType::JsonLines => "jsonl",
+            DocumentType::Csv => "csv",
+            DocumentType::Yaml => "yaml",
+            DocumentType::Toml => "toml",
+            DocumentType::Xml => "xml",
+        }
+    }
+
+    /// Maps the file extension onto a document type, ignoring case.
+    /// A compression extension is looked past, so `data.csv.gz` is csv.
+    pub fn from_extension(file_name: &str) -> Option<DocumentType> {
+        let file_name = Compression::strip_extension(file_name);
+        let extension = Path::new(file_name).extension()?.to_str()?;
+        match extension.to_ascii_lowercase().as_str() {
+            "json" => Some(DocumentType::Json),
+            "jsonl" | "ndjson" => Some(DocumentType::JsonLines),
+            "csv" => Some(DocumentType::Csv),
+            "yaml" | "yml" => Some(DocumentType::Yaml),
+            "toml" => Some(DocumentType::Toml),
+            "xml" => Some(DocumentType::Xml),
+            _ => None,
+        }
+    }
+
+    /// Trusts the extension when there is a known one, otherwise sniffs the start of the file.
+    pub fn detect(file_name: &str) -> Result<DocumentType, DocumentError> {
+        match Self::from_extension(file_name) {
+            Some(doc_type) => Ok(doc_type),
+            None => Self::sniff_file(file_name),
+        }
+    }
+
+    /// Works out the document type from the first bytes of the file alone.
+    pub fn sniff_file(file_name: &str) -> Result<DocumentType, DocumentError> {
+        let head = read_head(file_name).map_err(|err| DocumentError::io(file_name, err))?;
+        Self::sniff(&head).map_err(|reason| {
+            DocumentError::unsupported_format(
+                file_name,
+                format!("cannot detect the format, {}", reason),
+            )
+        })
+    }
+
+    fn sniff(head: &[u8]) -> Result<DocumentType, String> {
+        let lines = sample_lines(head, 10);
+        let head = head.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(head);
+        if head.starts_with(b"---") || head.starts_with(b"%YAML") {
+            return Ok(DocumentType::Yaml);
+        }
+        // Before json, which also starts with `[`.
+        if is_toml_table(head) {
+            return Ok(DocumentType::Toml);
+        }
+        match head.trim_ascii_start().first() {
+            None => return Err("the file is empty".to_string()),
+            Some(b'{' | b'[') => return Ok(DocumentType::Json),
+            Some(b'<') => return Ok(DocumentType::Xml),
+            Some(_) => {}
+        }
+
+        if lines.is_empty() {
+            return Err(format!("no complete line in the first {} bytes", SNIFF_LEN));
+        }
+        let fitting: Vec<u8> = csv_processor::DELIMITERS
+            .into_iter()
+            .filter(|&delimiter| {
+                let first = csv_processor::count_unquoted(lines[0], delimiter, b'"');
+                first > 0
+                    && lines
+                        .iter()
+                        .all(|line| csv_processor::count_unquoted(line, delimiter, b'"') == first)
+            })
+            .collect();
+        match fitting.as_slice() {
+            [] => Err("it is neither json nor delimited text".to_string()),
+            [b','] => Ok(DocumentType::Csv),
+            [delimiter] => Err(format!(
+                "it looks like text separated by {:?}, open it with a CsvDialect using that delimiter",
+                *delimiter as char
+            )),
+            _ => Err(format!(
+                "it is ambiguous, rows split evenly on each of {:?}",
+                fitting.iter().map(|&b| b as char).collect::<Vec<_>>()
+            )),
+        }
+    }
+}
+
+/// Whether the first line that is neither blank nor a comment is a toml table header like
+/// `[name]` or `[[records]]`. Lines like `[1]` or `[true]` are json arrays instead.
+fn is_toml_table(head: &[u8]) -> bool {
+    let Some(line) = head
+        .split(|&b| b == b'\n')
+        .map(<[u8]>::trim_ascii)
+        .find(|line| !line.is_empty() && !line.starts_with(b"#"))
+    else {
+        return false;
+    };
+    // Bare keys cannot hold `#`, so one starts a comment.
+    let line = line
+        .split(|&b| b == b'#')
+        .next()
+        .unwrap_or(line)
+        .trim_ascii();
+    let name = line
+        .strip_prefix(b"[[")
+        .and_then(|line| line.strip_suffix(b"]]"))
+        .or_else(|| {
+            line.strip_prefix(b"[")
+                .and_then(|line| line.strip_suffix(b"]"))
+        });
+    let is_bare_key = |key: &[u8]| {
+        let key = key.trim_ascii();
+        !key.is_empty()
+            && key
+                .iter()
+                .all(|&b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+    };
+    name.is_some_and(|name| name.split(|&b| b == b'.').all(is_bare_key))
+        && serde_json::from_slice::<Value>(line).is_err()
+}
+
+/// The first `SNIFF_LEN` bytes of the file, or less when the file is shorter.
+/// A compressed file is decompressed first, and text in another encoding converted to UTF-8.
+fn read_head(file_name: &str) -> io::Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    let source = Compression::decode(Box::new(File::open(file_name)?))?;
+    Encoding::decode(source)?
+        .1
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut head)?;
+    Ok(head)
+}
+
+/// The value at a dotted path like `address.zip` in the serde representation of a record,
+/// the way filters, rules, diffs and edits name fields.
+fn field_at<'a>(record: &'a Value, path: &str) -> Option<&'a Value> {
+    field_at_names(record, path.split('.'))
+}
+
+/// Like `field_at`, for a path already split into names, which may then contain dots.
+fn field_at_names<'a, 'n>(
+    record: &'a Value,
+    names: impl IntoIterator<Item = &'n str>,
+) -> Option<&'a Value> {
+    names
+        .into_iter()
+        .try_fold(record, |value, name| value.get(name))
+}
+
+/// A number, or text that spells one, as csv and xml hold numbers. `inf` and `nan` are
+/// words before they are numbers, so they stay text.
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => text
+            .trim()
+            .parse()
+            .ok()
+            .filter(|number: &f64| number.is_finite()),
+        _ => None,
+    }
+}
+
+/// Whether two values are the same, with text that spells a number taken as that number,
+/// so the csv cell `"2"` is the json number `2`.
+fn same_value(left: &Value, right: &Value) -> bool {
+    left == right
+        || matches!((as_number(left), as_number(right)), (Some(left), Some(right)) if left == right)
+}
+
+/// The first `len` bytes of a source that cannot be opened twice. The head is put back
+/// in front of the rest, so the returned reader still starts at the first byte.
+fn peek_head<'a>(
+    mut source: Box<dyn Read + 'a>,
+    len: usize,
+) -> io::Result<(Vec<u8>, Box<dyn Read + 'a>)> {
+    let mut head = Vec::with_capacity(len);
+    (&mut source).take(len as u64).read_to_end(&mut head)?;
+    let source = Box::new(io::Cursor::new(head.clone()).chain(source));
+    Ok((head, source))
+}
+
+/// Up to `max` non-blank lines from the start of a file, without the line endings.
+fn sample_lines(head: &[u8], max: usize) -> Vec<&[u8]> {
+    let truncated = head.len() == SNIFF_LEN;
+    let head = head.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(head);
+    let mut lines: Vec<&[u8]> = head
+        .split(|&b| b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .collect();
+    if truncated {
+        // The last line was most likely cut off by the sniff window.
+        lines.pop();
+    }
+    lines
+        .into_iter()
+        .filter(|line| !line.trim_ascii().is_empty())
+        .take(max)
+        .collect()
+}
+
+/// Reads and writes the records of one document format. The record type is up to the
+/// caller, as long as serde can read and write it.
+pub trait DocumentProcessor<T: Record> {
+    /// Reads records from any source, e.g. stdin, a buffer in memory or a socket.
+    /// The source can be borrowed, like a `&[u8]` or an entry of an archive being read,
+    /// and the records are then read while it lives. `name` stands in for the file name in errors.
+    fn read_from<'a>(
+        &self,
+        source: Box<dyn Read + 'a>,
+        name: &str,
+    ) -> Result<RecordIter<'a, T>, DocumentError>;
+
+    /// Writes records to any sink. `name` stands in for the file name in errors.
+    fn writer_to(
+        &self,
+        sink: Box<dyn Write>,
+        name: &str,
+    ) -> Result<Box<dyn RecordWriter<T>>, DocumentError>;
+
+    /// Like `writer_to`, for a sink that replaces an existing document, whose text starts
+    /// with `original`. Writers follow its layout where they can, like the indentation of
+    /// json, so that saving a document unchanged gives back the same text.
+    fn writer_like(
+        &self,
+        sink: Box<dyn Write>,
+        name: &str,
+        _original: &[u8],
+    ) -> Result<Box<dyn RecordWriter<T>>, DocumentError> {
+        self.writer_to(sink, name)
+    }
+
+    /// Tells this processor apart from others that would read other records out of the
+    /// same file, for caches of what it read. The type by default; a processor with
+    /// settings that change what it reads adds them.
+    fn cache_key(&self) -> String {
+        std::any::type_name::<Self>().to_string()
+    }
+
+    fn read_records(&self, file_name: String) -> Result<RecordIter<'static, T>, DocumentError> {
+        let file = File::open(&file_name).map_err(|err| DocumentError::io(&file_name, err))?;
+        self.read_from(Box::new(file), &file_name)
+    }
+
+    fn writer(&self, file_name: String) -> Result<Box<dyn RecordWriter<T>>, DocumentError> {
+        let file = File::create(&file_name).map_err(|err| DocumentError::io(&file_name, err))?;
+        self.writer_to(Box::new(DurableFile(file)), &file_name)
+    }
+
+    /// The first record from `source`.
+    fn read_data_from<'a>(
+        &self,
+        source: Box<dyn Read + 'a>,
+        name: &str,
+    ) -> Result<T, DocumentError> {
+        match self.read_from(source, name)?.next() {
+            Some(record) => record,
+            None => Err(DocumentError::EmptyDocument {
+                path: name.to_string(),
+            }),
+        }
+    }
+
+    fn read_data(&self, file_name: String) -> Result<T, DocumentError> {
+        match self.read_records(file_name.clone())?.next() {
+            Some(record) => record,
+            None => Err(DocumentError::EmptyDocument { path: file_name }),
+        }
+    }
+
+    fn write_data(&self, file_name: String, records: &[T]) -> Result<(), DocumentError> {
+        let mut writer = self.writer(file_name)?;
+        for record in records {
+            writer.write_record(record)?;
+        }
+        writer.finish()
+    }
+}
+
+/// Reads the records of one document, checked against a validator when it has one, and
+/// writes them back. For editing, the document can also be held in memory, where records
+/// are inserted, updated and deleted, undone and redone, one at a time or in transactions.
+pub struct DocumentEditor<T: Record> {
+    file_name: String,
+    processor: Box<dyn DocumentProcessor<T>>,
+    validator: Option<Rc<dyn RecordValidator<T>>>,
+    /// Read and write in this encoding instead of detecting it.
+    encoding: Option<Encoding>,
+    keep_encoding: bool,
+    /// The document held in memory once it is loaded for editing.
+    records: Option<Vec<T>>,
+    history: History<T>,
+}
+
+impl<T: Record> DocumentEditor<T> {
+    fn new(file_name: String, processor: Box<dyn DocumentProcessor<T>>) -> Self {
+        DocumentEditor {
+            file_name,
+            processor,
+            validator: None,
+            encoding: None,
+            keep_encoding: false,
+            records: None,
+            history: History::new(history::DEFAULT_DEPTH),
+        }
+    }
+
+    /// Checks every record read through this editor against `validator`. A record that
+    /// breaks any rule is reported as `DocumentError::Validation` with all its violations.
+    pub fn with_validator(mut self, validator: impl RecordValidator<T> + 'static) -> Self {
+        self.validator = Some(Rc::new(validator));
+        self
+    }
+
+    /// Reads and writes the document in `encoding`, whatever its bytes look like.
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = Some(encoding);
+        self
+    }
+
+    /// Saves the document in the encoding it has now, instead of UTF-8.
+    pub fn keep_encoding(mut self) -> Self {
+        self.keep_encoding = true;
+        self
+    }
+
+    /// The encoding the document is read in, detected from the file unless one was set.
+    pub fn encoding(&self) -> Result<Encoding, DocumentError> {
+        match self.encoding {
+            Some(encoding) => Ok(encoding),
+            None => Encoding::detect(&self.file_name)
+                .map_err(|err| DocumentError::io(&self.file_name, err)),
+        }
+    }
+
+    /// The document file, decompressed when it is compressed, and converted to UTF-8.
+    fn open(&self) -> Result<Box<dyn Read>, DocumentError> {
+        let io_error = |err| DocumentError::io(&self.file_name, err);
+        let file = File::open(&self.file_name).map_err(io_error)?;
+        decode(Box::new(file), self.encoding).map_err(io_error)
+    }
+
+    /// The start of the document as it is before a save, and whether it ends with a
+    /// newline. `None` when there is no document yet, or it cannot be read, in which
+    /// case the save simply writes a fresh one.
+    fn original(&self) -> Option<(Vec<u8>, bool)> {
+        let mut source = self.open().ok()?;
+        let mut head = Vec::with_capacity(SNIFF_LEN);
+        (&mut source)
+            .take(SNIFF_LEN as u64)
+            .read_to_end(&mut head)
+            .ok()?;
+        let mut last = head.last().copied();
+        let mut chunk = [0; 8 * 1024];
+        loop {
+            match source.read(&mut chunk).ok()? {
+                0 => break,
+                len => last = Some(chunk[len - 1]),
+            }
+        }
+        Some((head, last == Some(b'\n')))
+    }
+
+    pub fn read_data(&self) -> Result<T, DocumentError> {
+        let record = self
+            .processor
+            .read_data_from(self.open()?, &self.file_name)?;
+        check_record(self.validator.as_deref(), &self.file_name, 0, record)
+    }
+
+    pub fn read_records(&self) -> Result<RecordIter<'static, T>, DocumentError> {
+        self.records_from(self.open()?)
+    }
+
+    /// How the editor reads its document: the processor, the encoding and the validator.
+    /// Editors with the same key get the same records out of the same file.
+    fn cache_key(&self) -> String {
+        let validator = self
+            .validator
+            .as_ref()
+            .map_or_else(String::new, |validator| validator.cache_key());
+        format!(
+            "{} {:?} {}",
+            self.processor.cache_key(),
+            self.encoding,
+            validator
+        )
+    }
+
+    /// The records in `source`, an opened document, validated like `read_records` does.
+    fn records_from<'a>(
+        &self,
+        source: Box<dyn Read + 'a>,
+    ) -> Result<RecordIter<'a, T>, DocumentError> {
+        let records = self.processor.read_from(source, &self.file_name)?;
+        let Some(validator) = self.validator.clone() else {
+            return Ok(records);
+        };
+        let file_name = self.file_name.clone();
+        Ok(Box::new(records.enumerate().map(move |(index, record)| {
+            check_record(Some(&*validator), &file_name, index, record?)
+        })))
+    }
+
+    /// Replaces the document with `records`. The records go to a temp file next to the
+    /// document first, which is then renamed over it, so readers never see a half written file.
+    pub fn save(&self, records: &[T]) -> Result<(), DocumentError> {
+        self.save_with(|writer| {
+            for record in records {
+                writer.write_record(record)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Like `save`, but `write` streams the records into the writer itself. The document
+    /// is only replaced when `write` and closing the writer both succeed.
+    /// A compressed document stays compressed the same way, a new one is compressed
+    /// when its extension asks for it. The text is UTF-8 unless the editor was told
+    /// to keep the document's encoding or use another one. The writer follows the layout
+    /// of the document it replaces, down to whether it ends with a newline.
+    pub fn save_with<R, F>(&self, write: F) -> Result<R, DocumentError>
+    where
+        F: FnOnce(&mut dyn RecordWriter<T>) -> Result<R, DocumentError>,
+    {
+        let (compression, encoding) =
+            save_format(&self.file_name, self.encoding, self.keep_encoding)?;
+        let original = self.original();
+        let temp_name = temp_file_name(&self.file_name);
+        let result = File::create(&temp_name)
+            .and_then(|file| compression.encode(Box::new(DurableFile(file))))
+            .map(|sink| encoding.encoder(sink))
+            .map_err(|err| DocumentError::io(&temp_name, err))
+            .and_then(|sink| match &original {
+                Some((head, false)) if !head.is_empty() => self.processor.writer_like(
+                    Box::new(NoFinalNewline::new(sink)),
+                    &temp_name,
+                    head,
+                ),
+                Some((head, _)) => self.processor.writer_like(sink, &temp_name, head),
+                None => self.processor.writer_to(sink, &temp_name),
+            })
+            .and_then(|mut writer| {
+                let written = write(writer.as_mut())?;
+                writer.finish()?;
+                Ok(written)
+            });
+        match result {
+            Ok(written) => {
+                fs::rename(&temp_name, &self.file_name)
+                    .map_err(|err| DocumentError::io(&self.file_name, err))?;
+                Ok(written)
+            }
+            Err(err) => {
+                let _ = fs::remove_file(&temp_name);
+                Err(err.with_path(&self.file_name))
+            }
+        }
+    }
+}
+
+/// The bytes of a document file as the processors read them: decompressed when they are
+/// compressed, and converted to UTF-8 from `encoding`, or from the encoding they are found to be in.
+fn decode<'a>(
+    source: Box<dyn Read + 'a>,
+    encoding: Option<Encoding>,
+) -> io::Result<Box<dyn Read + 'a>> {
+    let source = Compression::decode(source)?;
+    match encoding {
+        Some(encoding) => encoding.decoder(source),
+        None => Encoding::decode(source).map(|(_, source)| source),
+    }
+}
+
+/// How a save replaces `file_name`: compressed the way it is, or its extension asks for,
+/// and in UTF-8, unless another encoding is set or the one it has is to be kept.
+fn save_format(
+    file_name: &str,
+    encoding: Option<Encoding>,
+    keep_encoding: bool,
+) -> Result<(Compression, Encoding), DocumentError> {
+    let io_error = |err| DocumentError::io(file_name, err);
+    let compression = Compression::detect(file_name).map_err(io_error)?;
+    let encoding = match (encoding, keep_encoding) {
+        (None, false) => Encoding::Utf8,
+        (Some(encoding), _) => encoding,
+        (None, true) => Encoding::detect(file_name).map_err(io_error)?,
+    };
+    Ok((compression, encoding))
+}
+
+/// Holds back the line ending at the end of everything written so far, and only passes
+/// it on once more text follows, so the document ends without one.
+struct NoFinalNewline {
+    sink: Box<dyn Write>,
+    held: Vec<u8>,
+}
+
+impl NoFinalNewline {
+    fn new(sink: Box<dyn Write>) -> Self {
+        NoFinalNewline {
+            sink,
+            held: Vec::new(),
+        }
+    }
+}
+
+impl Write for NoFinalNewline {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut text = std::mem::take(&mut self.held);
+        text.extend_from_slice(buf);
+        let ending = if text.ends_with(b"\r\n") {
+            2
+        } else if text.ends_with(b"\n") || text.ends_with(b"\r") {
+            1
+        } else {
+            0
+        };
+        let (body, ending) = text.split_at(text.len() - ending);
+        self.sink.write_all(body)?;
+        self.held = ending.to_vec();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.sink.flush()
+    }
+}
+
+fn check_record<T: Record>(
+    validator: Option<&dyn RecordValidator<T>>,
+    file_name: &str,
+    index: usize,
+    record: T,
+) -> Result<T, DocumentError> {
+    let violations = validator.map_or_else(Vec::new, |validator| validator.validate(&record));
+    if violations.is_empty() {
+        Ok(record)
+    } else {
+        Err(DocumentError::Validation {
+            path: file_name.to_string(),
+            record: index,
+            violations,
+        })
+    }
+}
+
+/// A hidden sibling of `file_name`, so the final rename never crosses a filesystem boundary.
+/// The process id and a counter keep saves from any two threads or processes apart.
+fn temp_file_name(file_name: &str) -> String {
+    static SAVES: AtomicUsize = AtomicUsize::new(0);
+    let path = Path::new(file_name);
+    let base = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let save = SAVES.fetch_add(1, Ordering::Relaxed);
+    path.with_file_name(format!(".{}.{}.{}.tmp", base, process::id(), save))
+        .to_string_lossy()
+        .into_owned()
+}
+
+pub struct DocumentEditorFactory {}
+
+impl DocumentEditorFactory {
+    pub fn create_editor<T: Record>(
+        file_name: String,
+        doc_type: DocumentType,
+    ) -> DocumentEditor<T> {
+        Self::create_editor_with(&ProcessorRegistry::default(), file_name, doc_type.name())
+            .expect("built-in document types are always registered")
+    }
+
+    /// Builds a csv editor that reads and writes `dialect` instead of plain comma separated csv.
+    pub fn create_csv_editor<T: Record>(
+        file_name: String,
+        dialect: CsvDialect,
+    ) -> DocumentEditor<T> {
+        DocumentEditor::new(file_name, Box::new(CsvProcessor::new(dialect)))
+    }
+
+    /// An editor for async code. Only csv and json have an async processor.
+    pub fn create_async_editor<T: Record + Send + Sync>(
+        file_name: String,
+        doc_type: DocumentType,
+    ) -> Result<AsyncDocumentEditor<T>, DocumentError> {
+        let processor: Arc<dyn AsyncDocumentProcessor<T>> = match doc_type {
+            DocumentType::Csv => Arc::new(CsvProcessor::default()),
+            DocumentType::Json => Arc::new(JsonProcessor {}),
+            _ => {
+                return Err(DocumentError::unsupported_format(
+                    &file_name,
+                    format!("there is no async processor for {}", doc_type.name()),
+                ));
+            }
+        };
+        Ok(AsyncDocumentEditor::new(file_name, processor))
+    }
+
+    /// Like `create_csv_editor`, for async code.
+    pub fn create_async_csv_editor<T: Record + Send + Sync>(
+        file_name: String,
+        dialect: CsvDialect,
+    ) -> AsyncDocumentEditor<T> {
+        AsyncDocumentEditor::new(file_name, Arc::new(CsvProcessor::new(dialect)))
+    }
+
+    /// Like `create_editor`, with the document type worked out by `DocumentType::detect`.
+    pub fn create_editor_auto<T: Record>(
+        file_name: String,
+    ) -> Result<DocumentEditor<T>, DocumentError> {
+        Self::create_editor_auto_with(&ProcessorRegistry::default(), file_name)
+    }
+
+    /// Builds an editor around the processor registered under `format`.
+    pub fn create_editor_with<T: Record>(
+        registry: &ProcessorRegistry<T>,
+        file_name: String,
+        format: &str,
+    ) -> Result<DocumentEditor<T>, DocumentError> {
+        let processor = registry.create(format).ok_or_else(|| {
+            DocumentError::unsupported_format(
+                &file_name,
+                format!(
+                    "no document processor is registered for format `{}`",
+                    format
+                ),
+            )
+        })?;
+        Ok(DocumentEditor::new(file_name, processor))
+    }
+
+    /// Picks the format registered for the file extension, falling back to sniffing the content.
+    pub fn create_editor_auto_with<T: Record>(
+        registry: &ProcessorRegistry<T>,
+        file_name: String,
+    ) -> Result<DocumentEditor<T>, DocumentError> {
+        let registered = Path::new(Compression::strip_extension(&file_name))
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(|extension| registry.format_for_extension(extension))
+            .map(str::to_string);
+        let format = match registered {
+            Some(format) => format,
+            None => DocumentType::sniff_file(&file_name)?.name().to_string(),
+        };
+        Self::create_editor_with(registry, file_name, &format)
+    }
+}
+
+pub fn run() {
+    let file_info_list: Vec<(&str, DocumentType)> = vec![
+        ("data.json", DocumentType::Json),
+        ("data.csv", DocumentType::Csv),
+    ];
+    for file_info in file_info_list {
+        let document_editor: DocumentEditor<DocData> =
+            DocumentEditorFactory::create_editor(file_info.0.to_string(), file_info.1)
+                .with_validator(DerivedRules);
+        let records = document_editor.read_records().expect("Error opening doc");
+        for doc_data in records {
+            let doc_data = doc_data.expect("Error reading data from doc");
+            println!("file :: {}, doc_data :: {:?}", file_info.0, doc_data);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_files::TempDir;
+
+    #[test]
+    fn toml_table_headers_are_not_taken_for_json() {
+        let sniff = |head: &str| DocumentType::sniff(head.as_bytes());
+        assert_eq!(
+            sniff("[[records]]\nname = \"ann\"\n"),
+            Ok(DocumentType::Toml)
+        );
+        assert_eq!(
+            sniff("# people\n\n[owner.address]\ncity = \"x\"\n"),
+            Ok(DocumentType::Toml)
+        );
+        assert_eq!(
+            sniff("[ server ] # main\nport = 80\n"),
+            Ok(DocumentType::Toml)
+        );
+        assert_eq!(sniff("[1, 2]\n"), Ok(DocumentType::Json));
+        assert_eq!(sniff("[1]\n"), Ok(DocumentType::Json));
+        assert_eq!(sniff("[true]"), Ok(DocumentType::Json));
+        assert_eq!(sniff("[{\"name\": \"ann\"}]"), Ok(DocumentType::Json));
+        assert_eq!(
+            sniff("[\n  {\n    \"name\": \"ann\"\n  }\n]\n"),
+            Ok(DocumentType::Json)
+        );
+        assert_eq!(sniff("[\"records\"]\n"), Ok(DocumentType::Json));
+    }
+
+    /// Reads `contents` back from a file called `name` and saves the records again.
+    fn round_trip(name: &str, doc_type: DocumentType, contents: &str) -> String {
+        let dir = TempDir::new();
+        let path = dir.write(name, contents);
+        let editor: DocumentEditor<DocData> = DocumentEditorFactory::create_editor(path, doc_type);
+        let records = editor
+            .read_records()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        editor.save(&records).unwrap();
+        String::from_utf8(dir.read(name)).unwrap()
+    }
+
+    #[test]
+    fn saving_what_was_read_gives_the_same_file_back() {
+        let cases = [
+            (
+                "compact.json",
+                DocumentType::Json,
+                "[{\"name\":\"ann\",\"age\":31},{\"name\":\"bob\",\"age\":42}]\n",
+            ),
+            (
+                "indented.json",
+                DocumentType::Json,
+                "[\n    {\n        \"name\": \"ann\",\n        \"age\": 31\n    },\n    {\n        \"name\": \"bob\",\n        \"age\": 42\n    }\n]\n",
+            ),
+            (
+                "single.json",
+                DocumentType::Json,
+                "{\n  \"name\": \"ann\",\n  \"age\": 31\n}\n",
+            ),
+            (
+                "single.json",
+                DocumentType::Json,
+                "[\n  {\n    \"name\": \"ann\",\n    \"age\": 31\n  }\n]\n",
+            ),
+            (
+                "crlf.csv",
+                DocumentType::Csv,
+                "name,age\r\nann,31\r\nbob,42\r\n",
+            ),
+            ("unix.csv", DocumentType::Csv, "name,age\nann,31\nbob,42\n"),
+            (
+                "no_newline.csv",
+                DocumentType::Csv,
+                "name,age\nann,31\nbob,42",
+            ),
+            (
+                "no_newline.json",
+                DocumentType::Json,
+                "[{\"name\":\"ann\",\"age\":31}]",
+            ),
+        ];
+        for (name, doc_type, contents) in cases {
+            assert_eq!(round_trip(name, doc_type, contents), contents, "{}", name);
+        }
+    }
+
+    #[test]
+    fn a_failed_save_leaves_the_document_and_no_temp_file() {
+        let dir = TempDir::new();
+        let contents = "name,age\nann,31\n";
+        let path = dir.write("people.csv", contents);
+        let editor: DocumentEditor<DocData> =
+            DocumentEditorFactory::create_editor(path.clone(), DocumentType::Csv);
+        let result: Result<(), _> = editor.save_with(|writer| {
+            writer.write_record(&DocData {
+                name: "bob".to_string(),
+                age: 42,
+            })?;
+            Err(DocumentError::io(&path, io::ErrorKind::WriteZero.into()))
+        });
+        assert!(matches!(result, Err(DocumentError::Io { path: failed, .. }) if failed == path));
+        assert_eq!(dir.read("people.csv"), contents.as_bytes());
+        assert_eq!(dir.names(), ["people.csv"]);
+    }
+}
@@ -0,0 +1,91 @@
+use super::{
+    CsvProcessor, DocumentProcessor, JsonLinesProcessor, JsonProcessor, Record, TomlProcessor,
+    XmlProcessor, YamlProcessor,
+};
+use std::collections::HashMap;
+
+/// Builds a fresh processor each time an editor asks for one.
+pub type ProcessorConstructor<T> = Box<dyn Fn() -> Box<dyn DocumentProcessor<T>> + Send + Sync>;
+
+struct ProcessorEntry<T: Record> {
+    extensions: Vec<String>,
+    constructor: ProcessorConstructor<T>,
+}
+
+/// Document processors keyed by format name, so new formats can be plugged in
+/// without touching `DocumentType` or `DocumentEditorFactory`.
+/// Names and extensions are matched case-insensitively.
+pub struct ProcessorRegistry<T: Record> {
+    entries: HashMap<String, ProcessorEntry<T>>,
+}
+
+impl<T: Record> ProcessorRegistry<T> {
+    /// A registry without any formats, not even the built-in ones.
+    pub fn new() -> Self {
+        ProcessorRegistry {
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Registers `constructor` under `name`, replacing any processor already registered there.
+    /// When two formats claim the same extension, the one registered last wins.
+    pub fn register<F>(&mut self, name: &str, extensions: &[&str], constructor: F)
+    where
+        F: Fn() -> Box<dyn DocumentProcessor<T>> + Send + Sync + 'static,
+    {
+        let extensions: Vec<String> = extensions
+            .iter()
+            .map(|extension| extension.trim_start_matches('.').to_ascii_lowercase())
+            .collect();
+        for entry in self.entries.values_mut() {
+            entry.extensions.retain(|known| !extensions.contains(known));
+        }
+        self.entries.insert(
+            name.to_ascii_lowercase(),
+            ProcessorEntry {
+                extensions,
+                constructor: Box::new(constructor),
+            },
+        );
+    }
+
+    pub fn create(&self, name: &str) -> Option<Box<dyn DocumentProcessor<T>>> {
+        self.entries
+            .get(&name.to_ascii_lowercase())
+            .map(|entry| (entry.constructor)())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(&name.to_ascii_lowercase())
+    }
+
+    /// The name of the format registered for `extension`, given with or without the leading dot.
+    pub fn format_for_extension(&self, extension: &str) -> Option<&str> {
+        let extension = extension.trim_start_matches('.').to_ascii_lowercase();
+        self.entries
+            .iter()
+            .find(|(_, entry)| entry.extensions.contains(&extension))
+            .map(|(name, _)| name.as_str())
+    }
+
+    pub fn formats(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+}
+
+impl<T: Record> Default for ProcessorRegistry<T> {
+    /// A registry with the built-in csv, json, json lines, yaml, toml and xml processors.
+    /// The xml processor expects the default `<records><record ../></records>` layout.
+    fn default() -> Self {
+        let mut registry = ProcessorRegistry::new();
+        registry.register("csv", &["csv"], || Box::new(CsvProcessor::default()));
+        registry.register("json", &["json"], || Box::new(JsonProcessor {}));
+        registry.register("jsonl", &["jsonl", "ndjson"], || {
+            Box::new(JsonLinesProcessor {})
+        });
+        registry.register("yaml", &["yaml", "yml"], || Box::new(YamlProcessor {}));
+        registry.register("toml", &["toml"], || Box::new(TomlProcessor {}));
+        registry.register("xml", &["xml"], || Box::new(XmlProcessor::default()));
+        registry
+    }
+}
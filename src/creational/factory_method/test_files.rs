@@ -0,0 +1,52 @@
+use std::{
+    env, fs,
+    path::PathBuf,
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// A directory of its own for a test that needs real files, removed again when dropped.
+pub(super) struct TempDir(PathBuf);
+
+impl TempDir {
+    pub(super) fn new() -> Self {
+        static DIRS: AtomicUsize = AtomicUsize::new(0);
+        let dir = DIRS.fetch_add(1, Ordering::Relaxed);
+        let path = env::temp_dir().join(format!("lld-rust-test.{}.{}", process::id(), dir));
+        fs::create_dir_all(&path).expect("the temp dir can be created");
+        TempDir(path)
+    }
+
+    /// The path of `name` in the directory, as the editors take it.
+    pub(super) fn path(&self, name: &str) -> String {
+        self.0.join(name).to_string_lossy().into_owned()
+    }
+
+    /// Writes `contents` to `name` and returns its path.
+    pub(super) fn write(&self, name: &str, contents: impl AsRef<[u8]>) -> String {
+        let path = self.path(name);
+        fs::write(&path, contents).expect("the test file can be written");
+        path
+    }
+
+    pub(super) fn read(&self, name: &str) -> Vec<u8> {
+        fs::read(self.path(name)).expect("the test file can be read")
+    }
+
+    /// The names of the files in the directory, sorted.
+    pub(super) fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(&self.0)
+            .expect("the temp dir can be listed")
+            .map(|entry| entry.expect("the temp dir can be listed").file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,103 @@
+use serde::de::{self, Deserializer, Expected, IntoDeserializer, Unexpected, Visitor};
+
+/// The text of a field in a format without types, like a csv cell or an xml attribute.
+/// Numbers and booleans are parsed out of the text when the record asks for them, and
+/// anything that takes whatever it is given, like `DynamicRecord`, gets the text as is.
+pub(super) struct TextValue {
+    /// Names the field in errors.
+    field: String,
+    text: String,
+}
+
+impl TextValue {
+    pub(super) fn new(field: impl Into<String>, text: impl Into<String>) -> Self {
+        TextValue {
+            field: field.into(),
+            text: text.into(),
+        }
+    }
+
+    fn invalid(&self, expected: &dyn Expected) -> de::value::Error {
+        de::Error::custom(format_args!(
+            "field `{}` holds {}, expected {}",
+            self.field,
+            Unexpected::Str(&self.text),
+            expected
+        ))
+    }
+}
+
+impl<'de> IntoDeserializer<'de, de::value::Error> for TextValue {
+    type Deserializer = TextValue;
+
+    fn into_deserializer(self) -> TextValue {
+        self
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                match self.text.trim().parse() {
+                    Ok(value) => visitor.$visit(value),
+                    Err(_) => Err(self.invalid(&visitor)),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for TextValue {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_string(self.text)
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        if self.text.is_empty() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_enum(self.text.into_deserializer())
+    }
+
+    serde::forward_to_deserialize_any! {
+        char str string bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
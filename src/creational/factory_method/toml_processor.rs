@@ -0,0 +1,126 @@
+use super::{DocumentError, DocumentProcessor, Location, Record, RecordIter, RecordWriter};
+use std::io::{BufWriter, Read, Write};
+
+/// The array of tables that holds the records when a toml document has more than one.
+const RECORDS_KEY: &str = "records";
+
+pub struct TomlProcessor {}
+
+/// toml reports the byte span of a syntax error, which is turned into a line and column here.
+fn parse_error(path: &str, text: &str, err: toml::de::Error) -> DocumentError {
+    let location = err.span().map_or_else(Location::default, |span| {
+        let before = &text[..span.start.min(text.len())];
+        let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+        Location {
+            line: Some(before.matches('\n').count() + 1),
+            column: Some(before[line_start..].chars().count() + 1),
+            offset: Some(span.start as u64),
+        }
+    });
+    DocumentError::parse(path, location, err.message())
+}
+
+impl<T: Record> DocumentProcessor<T> for TomlProcessor {
+    /// A document with a `[[records]]` array of tables yields one record per table,
+    /// any other document is read as a single record from its top level table.
+    /// toml has no streaming parser, so the whole file is read first.
+    fn read_from<'a>(
+        &self,
+        mut source: Box<dyn Read + 'a>,
+        name: &str,
+    ) -> Result<RecordIter<'a, T>, DocumentError> {
+        let file_name = name.to_string();
+        let mut text = String::new();
+        source
+            .read_to_string(&mut text)
+            .map_err(|err| DocumentError::io(&file_name, err))?;
+        let mut table: toml::Table =
+            toml::from_str(&text).map_err(|err| parse_error(&file_name, &text, err))?;
+        let records = match table.remove(RECORDS_KEY) {
+            Some(toml::Value::Array(tables))
+                if tables.iter().all(|value| value.is_table()) && table.is_empty() =>
+            {
+                tables
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, value)| (format!("{}[{}]", RECORDS_KEY, i), value))
+                    .collect()
+            }
+            Some(value) => {
+                table.insert(RECORDS_KEY.to_string(), value);
+                vec![("top level table".to_string(), toml::Value::Table(table))]
+            }
+            None => vec![("top level table".to_string(), toml::Value::Table(table))],
+        };
+        // Values taken out of the table have lost their spans, so the record is named instead.
+        Ok(Box::new(records.into_iter().map(
+            move |(table_name, value)| {
+                value.try_into::<T>().map_err(|err| {
+                    DocumentError::schema_mismatch(
+                        &file_name,
+                        Location::default(),
+                        format!("{}: {}", table_name, err.message()),
+                    )
+                })
+            },
+        )))
+    }
+
+    fn writer_to(
+        &self,
+        sink: Box<dyn Write>,
+        name: &str,
+    ) -> Result<Box<dyn RecordWriter<T>>, DocumentError> {
+        Ok(Box::new(TomlRecordWriter {
+            file_name: name.to_string(),
+            out: BufWriter::new(sink),
+            tables: Vec::new(),
+        }))
+    }
+}
+
+/// Writes a lone record as the top level table and several records as `[[records]]`.
+/// Tables nested in a record have to be re-rooted under `records`, so unlike the other
+/// writers everything is collected and serialized in one go on `finish`.
+struct TomlRecordWriter {
+    file_name: String,
+    out: BufWriter<Box<dyn Write>>,
+    tables: Vec<toml::Value>,
+}
+
+impl<T: Record> RecordWriter<T> for TomlRecordWriter {
+    fn write_record(&mut self, record: &T) -> Result<(), DocumentError> {
+        let mismatch = |message: String| {
+            DocumentError::schema_mismatch(&self.file_name, Location::default(), message)
+        };
+        let value = toml::Value::try_from(record).map_err(|err| mismatch(err.to_string()))?;
+        if !value.is_table() {
+            return Err(mismatch(format!(
+                "a toml record has to be a table, not {}",
+                value.type_str()
+            )));
+        }
+        self.tables.push(value);
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), DocumentError> {
+        let text = if self.tables.len() == 1 {
+            toml::to_string(&self.tables[0])
+        } else {
+            let mut document = toml::Table::new();
+            document.insert(
+                RECORDS_KEY.to_string(),
+                toml::Value::Array(std::mem::take(&mut self.tables)),
+            );
+            toml::to_string(&document)
+        };
+        let file_name = self.file_name;
+        let text = text.map_err(|err| {
+            DocumentError::schema_mismatch(&file_name, Location::default(), err.to_string())
+        })?;
+        let io_error = |err| DocumentError::io(&file_name, err);
+        self.out.write_all(text.as_bytes()).map_err(io_error)?;
+        self.out.flush().map_err(io_error)
+    }
+}
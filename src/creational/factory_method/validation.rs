@@ -0,0 +1,253 @@
+use super::{Record, as_number, field_at, same_value};
+use regex::Regex;
+use serde_json::Value;
+use std::fmt;
+use validator::{Validate, ValidationErrors, ValidationErrorsKind};
+
+/// One rule a record breaks. `field` is a dotted path like `address.zip`,
+/// `rule` the name of the rule, e.g. `required`, `min` or `length`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub field: String,
+    pub rule: String,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+/// Checks a parsed record and returns every rule it breaks, not just the first one.
+pub trait RecordValidator<T: Record> {
+    fn validate(&self, record: &T) -> Vec<Violation>;
+
+    /// Tells this validator apart from others, for caches of validated records. The type
+    /// by default; a validator whose rules are set at runtime adds them.
+    fn cache_key(&self) -> String {
+        std::any::type_name::<Self>().to_string()
+    }
+}
+
+/// Runs the rules set with `validator`'s derive attributes, e.g.
+/// `#[validate(length(min = 1))]` or `#[validate(range(max = 150))]`.
+pub struct DerivedRules;
+
+impl<T: Record + Validate> RecordValidator<T> for DerivedRules {
+    fn validate(&self, record: &T) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        if let Err(errors) = record.validate() {
+            collect_derived("", &errors, &mut violations);
+        }
+        // `ValidationErrors` is a hash map, so the order is made stable here.
+        violations.sort_by(|a, b| a.field.cmp(&b.field));
+        violations
+    }
+}
+
+fn collect_derived(path: &str, errors: &ValidationErrors, violations: &mut Vec<Violation>) {
+    for (field, kind) in errors.errors() {
+        let path = if path.is_empty() {
+            field.to_string()
+        } else {
+            format!("{}.{}", path, field)
+        };
+        match kind {
+            ValidationErrorsKind::Field(errors) => violations.extend(errors.iter().map(|error| {
+                let message = match &error.message {
+                    Some(message) => message.to_string(),
+                    None => {
+                        let mut params: Vec<String> = error
+                            .params
+                            .iter()
+                            .map(|(name, value)| format!("{} = {}", name, value))
+                            .collect();
+                        params.sort();
+                        format!("breaks `{}` ({})", error.code, params.join(", "))
+                    }
+                };
+                Violation {
+                    field: path.clone(),
+                    rule: error.code.to_string(),
+                    message,
+                }
+            })),
+            ValidationErrorsKind::Struct(errors) => collect_derived(&path, errors, violations),
+            ValidationErrorsKind::List(items) => {
+                for (index, errors) in items {
+                    collect_derived(&format!("{}[{}]", path, index), errors, violations);
+                }
+            }
+        }
+    }
+}
+
+/// A check on a single field. Apart from `Required`, rules only look at fields that are
+/// present and not null, so optional fields are combined with `Required` when they must be set.
+#[derive(Debug, Clone)]
+pub enum Rule {
+    /// The field is present and not null.
+    Required,
+    /// A number that is at least this. Text that spells a number counts as that number,
+    /// as csv and xml hold them.
+    Min(f64),
+    /// A number that is at most this, like `Min`.
+    Max(f64),
+    /// Text matching the pattern somewhere. Anchor it with `^..$` to match the whole text.
+    Matches(Regex),
+    /// Text with this many characters, or a list with this many items.
+    Length {
+        min: Option<usize>,
+        max: Option<usize>,
+    },
+    /// One of the given values, with text that spells a number equal to that number.
+    OneOf(Vec<Value>),
+}
+
+impl Rule {
+    /// A `Matches` rule, or the error for a malformed pattern.
+    pub fn matches(pattern: &str) -> Result<Rule, regex::Error> {
+        Ok(Rule::Matches(Regex::new(pattern)?))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rule::Required => "required",
+            Rule::Min(_) => "min",
+            Rule::Max(_) => "max",
+            Rule::Matches(_) => "regex",
+            Rule::Length { .. } => "length",
+            Rule::OneOf(_) => "one_of",
+        }
+    }
+
+    /// Why `value` breaks the rule, or `None` when it does not.
+    fn check(&self, value: Option<&Value>) -> Option<String> {
+        let value = match value {
+            None | Some(Value::Null) => {
+                return matches!(self, Rule::Required).then(|| "is required".to_string());
+            }
+            Some(value) => value,
+        };
+        match self {
+            Rule::Required => None,
+            Rule::Min(min) => match as_number(value) {
+                Some(number) if number >= *min => None,
+                Some(number) => Some(format!("{} is less than {}", number, min)),
+                None => Some(format!("expected a number, found {}", value)),
+            },
+            Rule::Max(max) => match as_number(value) {
+                Some(number) if number <= *max => None,
+                Some(number) => Some(format!("{} is more than {}", number, max)),
+                None => Some(format!("expected a number, found {}", value)),
+            },
+            Rule::Matches(regex) => match value.as_str() {
+                Some(text) if regex.is_match(text) => None,
+                Some(text) => Some(format!("{:?} does not match `{}`", text, regex)),
+                None => Some(format!("expected text, found {}", value)),
+            },
+            Rule::Length { min, max } => {
+                let length = match value {
+                    Value::String(text) => text.chars().count(),
+                    Value::Array(items) => items.len(),
+                    _ => return Some(format!("expected text or a list, found {}", value)),
+                };
+                if min.is_some_and(|min| length < min) || max.is_some_and(|max| length > max) {
+                    let bound = match (min, max) {
+                        (Some(min), Some(max)) => format!("between {} and {}", min, max),
+                        (Some(min), None) => format!("at least {}", min),
+                        (None, _) => format!("at most {}", max.unwrap_or_default()),
+                    };
+                    Some(format!("has length {}, expected {}", length, bound))
+                } else {
+                    None
+                }
+            }
+            Rule::OneOf(allowed) => (!allowed.iter().any(|allowed| same_value(allowed, value)))
+                .then(|| {
+                    let allowed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+                    format!("{} is not one of {}", value, allowed.join(", "))
+                }),
+        }
+    }
+}
+
+/// Rules put together at runtime, e.g. from a config file. Records are looked at through
+/// their serde representation, so this works for any record type, `DynamicRecord` included.
+#[derive(Debug, Clone, Default)]
+pub struct RuleSet {
+    rules: Vec<(String, Rule)>,
+}
+
+impl RuleSet {
+    pub fn new() -> Self {
+        RuleSet::default()
+    }
+
+    /// Adds `rule` for `field`, a field name or a dotted path into nested records.
+    pub fn rule(mut self, field: &str, rule: Rule) -> Self {
+        self.rules.push((field.to_string(), rule));
+        self
+    }
+}
+
+impl<T: Record> RecordValidator<T> for RuleSet {
+    fn cache_key(&self) -> String {
+        format!("RuleSet{:?}", self.rules)
+    }
+
+    fn validate(&self, record: &T) -> Vec<Violation> {
+        let record = match serde_json::to_value(record) {
+            Ok(record) => record,
+            Err(err) => {
+                return vec![Violation {
+                    field: String::new(),
+                    rule: "serialize".to_string(),
+                    message: err.to_string(),
+                }];
+            }
+        };
+        self.rules
+            .iter()
+            .filter_map(|(field, rule)| {
+                rule.check(field_at(&record, field))
+                    .map(|message| Violation {
+                        field: field.clone(),
+                        rule: rule.name().to_string(),
+                        message,
+                    })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creational::factory_method::DynamicRecord;
+    use serde_json::json;
+
+    fn record(fields: Value) -> DynamicRecord {
+        serde_json::from_value(fields).unwrap()
+    }
+
+    #[test]
+    fn numeric_rules_take_csv_text_as_numbers() {
+        let rules = RuleSet::new()
+            .rule("age", Rule::Min(18.0))
+            .rule("age", Rule::Max(150.0))
+            .rule("id", Rule::OneOf(vec![json!(1), json!(2)]));
+        assert!(
+            rules
+                .validate(&record(json!({"age": "30", "id": "2"})))
+                .is_empty()
+        );
+        let violations = rules.validate(&record(json!({"age": " 9 ", "id": "3"})));
+        let messages: Vec<&str> = violations.iter().map(|v| v.message.as_str()).collect();
+        assert_eq!(messages, ["9 is less than 18", "\"3\" is not one of 1, 2"]);
+        let violations = rules.validate(&record(json!({"age": "old", "id": 1})));
+        assert_eq!(violations.len(), 2);
+        assert_eq!(violations[0].message, "expected a number, found \"old\"");
+    }
+}
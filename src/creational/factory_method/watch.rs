@@ -0,0 +1,263 @@
+use super::{DocumentEditor, DocumentError, Record};
+use std::{
+    fs, io, thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(200);
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Called with the records of the document after every change, or with the error
+/// that reading them ran into.
+type Subscriber<'a, T> = Box<dyn FnMut(Result<&[T], &DocumentError>) + 'a>;
+
+/// Identifies a subscriber, to unsubscribe it again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subscription(usize);
+
+/// What the file looked like at one poll. `None` while there is no file.
+///
+/// The file's identity is part of it, so a document replaced by renaming another file
+/// over it counts as changed even when the new file has the same size and time.
+#[derive(Debug, Clone, PartialEq)]
+struct Snapshot(Option<FileState>);
+
+#[derive(Debug, Clone, PartialEq)]
+struct FileState {
+    modified: Option<SystemTime>,
+    size: u64,
+    id: Option<(u64, u64)>,
+}
+
+impl Snapshot {
+    fn take(path: &str) -> Result<Snapshot, DocumentError> {
+        // Follows links, so a link switched to another file is seen as well.
+        match fs::metadata(path) {
+            Ok(metadata) => Ok(Snapshot(Some(FileState {
+                modified: metadata.modified().ok(),
+                size: metadata.len(),
+                id: file_id(&metadata),
+            }))),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Snapshot(None)),
+            Err(err) => Err(DocumentError::io(path, err)),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Watches the file of a `DocumentEditor` and hands the records to subscribers again
+/// whenever it changes. Created with `DocumentEditor::watch`.
+///
+/// Changes are found by polling the file's metadata, which works the same on every file
+/// system, network mounts included. A change is only read once the file has stayed the
+/// same for the debounce time, so a document written in several steps is read once, when
+/// it is complete. A file that disappears and settles that way is reported as an error;
+/// one that is deleted and written again, or replaced by a rename, within the debounce
+/// time is simply read again.
+///
+/// The editor is borrowed rather than moved to a thread of its own, so polling happens
+/// on the caller's thread, with `poll` or `run_until`.
+pub struct Watch<'a, T: Record> {
+    editor: &'a DocumentEditor<T>,
+    interval: Duration,
+    debounce: Duration,
+    subscribers: Vec<(Subscription, Subscriber<'a, T>)>,
+    next_subscription: usize,
+    /// The file as it was when subscribers were last told about it.
+    delivered: Snapshot,
+    /// The file as last polled, and since when it looks that way.
+    seen: Snapshot,
+    seen_since: Instant,
+}
+
+impl<T: Record> DocumentEditor<T> {
+    /// Starts watching the document for changes. The file as it is now counts as known,
+    /// so subscribers are first called after it changes.
+    pub fn watch(&self) -> Result<Watch<'_, T>, DocumentError> {
+        let snapshot = Snapshot::take(&self.file_name)?;
+        Ok(Watch {
+            editor: self,
+            interval: DEFAULT_POLL_INTERVAL,
+            debounce: DEFAULT_DEBOUNCE,
+            subscribers: Vec::new(),
+            next_subscription: 0,
+            delivered: snapshot.clone(),
+            seen: snapshot,
+            seen_since: Instant::now(),
+        })
+    }
+}
+
+impl<'a, T: Record> Watch<'a, T> {
+    /// How long `run_until` sleeps between two polls.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// How long the file has to stay the same before it is read.
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    pub fn subscribe(
+        &mut self,
+        subscriber: impl FnMut(Result<&[T], &DocumentError>) + 'a,
+    ) -> Subscription {
+        let subscription = Subscription(self.next_subscription);
+        self.next_subscription += 1;
+        self.subscribers.push((subscription, Box::new(subscriber)));
+        subscription
+    }
+
+    /// Returns false when the subscriber was already gone.
+    pub fn unsubscribe(&mut self, subscription: Subscription) -> bool {
+        let len = self.subscribers.len();
+        self.subscribers.retain(|(other, _)| *other != subscription);
+        self.subscribers.len() < len
+    }
+
+    /// Looks at the file once, and reads it and calls the subscribers when a change has
+    /// settled. Returns whether they were called. Errors are only returned for a file that
+    /// cannot even be looked at; errors reading it go to the subscribers.
+    pub fn poll(&mut self) -> Result<bool, DocumentError> {
+        let snapshot = Snapshot::take(&self.editor.file_name)?;
+        let now = Instant::now();
+        if snapshot != self.seen {
+            self.seen = snapshot;
+            self.seen_since = now;
+        }
+        if self.seen == self.delivered || now.duration_since(self.seen_since) < self.debounce {
+            return Ok(false);
+        }
+
+        // A write that lands while the document is read changes the snapshot again,
+        // so it is read once more on a later poll.
+        self.delivered = self.seen.clone();
+        let records = self
+            .editor
+            .read_records()
+            .and_then(|records| records.collect::<Result<Vec<T>, _>>());
+        for (_, subscriber) in &mut self.subscribers {
+            subscriber(records.as_deref());
+        }
+        Ok(true)
+    }
+
+    /// Polls the file until `stop` returns true, which is asked before every poll.
+    pub fn run_until(&mut self, mut stop: impl FnMut() -> bool) -> Result<(), DocumentError> {
+        while !stop() {
+            self.poll()?;
+            thread::sleep(self.interval);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creational::factory_method::{
+        DocumentEditorFactory, DocumentType, DynamicRecord, test_files::TempDir,
+    };
+    use std::{cell::RefCell, fs::File};
+
+    /// What subscribers were called with: the names in the records, or the error.
+    type Calls = RefCell<Vec<Result<Vec<String>, String>>>;
+
+    fn record(calls: &Calls) -> impl FnMut(Result<&[DynamicRecord], &DocumentError>) + '_ {
+        |records| {
+            calls.borrow_mut().push(
+                records
+                    .map(|records| {
+                        records
+                            .iter()
+                            .map(|record| record.get("name").unwrap().to_string())
+                            .collect()
+                    })
+                    .map_err(|err| err.to_string()),
+            )
+        }
+    }
+
+    fn editor(path: String) -> DocumentEditor<DynamicRecord> {
+        DocumentEditorFactory::create_editor(path, DocumentType::Csv)
+    }
+
+    #[test]
+    fn rewrites_renames_and_deletes_reach_subscribers() {
+        let dir = TempDir::new();
+        let path = dir.write("people.csv", "name\nann\n");
+        let editor = editor(path.clone());
+        let calls = Calls::default();
+        let mut watch = editor.watch().unwrap().with_debounce(Duration::ZERO);
+        watch.subscribe(record(&calls));
+        assert!(!watch.poll().unwrap());
+
+        dir.write("people.csv", "name\nann\nbob\n");
+        assert!(watch.poll().unwrap());
+        assert!(!watch.poll().unwrap());
+
+        // Same size and time as the file it replaces, so only its identity gives it away.
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        let other = dir.write("people.csv.new", "name\ncat\ndan\n");
+        File::options()
+            .write(true)
+            .open(&other)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        fs::rename(&other, &path).unwrap();
+        assert!(watch.poll().unwrap());
+
+        fs::remove_file(&path).unwrap();
+        assert!(watch.poll().unwrap());
+        assert!(!watch.poll().unwrap());
+
+        drop(watch);
+        let calls = calls.into_inner();
+        assert_eq!(
+            calls[..2],
+            [
+                Ok(vec!["\"ann\"".to_string(), "\"bob\"".to_string()]),
+                Ok(vec!["\"cat\"".to_string(), "\"dan\"".to_string()]),
+            ]
+        );
+        assert!(calls[2].as_ref().is_err_and(|err| err.contains(&path)));
+        assert_eq!(calls.len(), 3);
+    }
+
+    #[test]
+    fn changes_are_read_once_they_settle() {
+        let debounce = Duration::from_millis(50);
+        let dir = TempDir::new();
+        let path = dir.write("people.csv", "name\nann\n");
+        let editor = editor(path.clone());
+        let calls = Calls::default();
+        let mut watch = editor.watch().unwrap().with_debounce(debounce);
+        watch.subscribe(record(&calls));
+
+        // Deleted and written again before the debounce time is up: one read, of the new file.
+        fs::remove_file(&path).unwrap();
+        assert!(!watch.poll().unwrap());
+        dir.write("people.csv", "name\nbob\n\n");
+        assert!(!watch.poll().unwrap());
+        thread::sleep(debounce * 2);
+        assert!(watch.poll().unwrap());
+        assert!(!watch.poll().unwrap());
+
+        drop(watch);
+        assert_eq!(calls.into_inner(), [Ok(vec!["\"bob\"".to_string()])]);
+    }
+}
@@ -0,0 +1,418 @@
+use super::{
+    DocumentError, DocumentProcessor, Location, Record, RecordIter, RecordWriter,
+    text_value::TextValue,
+};
+use quick_xml::{
+    Reader,
+    escape::{escape, resolve_predefined_entity},
+    events::{BytesStart, Event},
+};
+use serde::de::value::MapDeserializer;
+use std::{
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    marker::PhantomData,
+};
+
+/// Reads records laid out as `<root><row field=".." ..>..</row>..</root>`.
+/// A field can be an attribute of the row element or a child element holding text,
+/// e.g. `<person name="obvious"><age>25</age></person>`.
+pub struct XmlProcessor {
+    pub root: String,
+    pub row: String,
+}
+
+impl XmlProcessor {
+    pub fn new(root: &str, row: &str) -> Self {
+        XmlProcessor {
+            root: root.to_string(),
+            row: row.to_string(),
+        }
+    }
+}
+
+impl Default for XmlProcessor {
+    fn default() -> Self {
+        XmlProcessor::new("records", "record")
+    }
+}
+
+impl<T: Record> DocumentProcessor<T> for XmlProcessor {
+    fn cache_key(&self) -> String {
+        format!("XmlProcessor <{}><{}>", self.root, self.row)
+    }
+
+    fn read_from<'a>(
+        &self,
+        source: Box<dyn Read + 'a>,
+        name: &str,
+    ) -> Result<RecordIter<'a, T>, DocumentError> {
+        let reader = Reader::from_reader(LineCounter::new(BufReader::new(source)));
+        Ok(Box::new(XmlRecords {
+            file_name: name.to_string(),
+            reader,
+            buf: Vec::new(),
+            root: self.root.clone(),
+            row: self.row.clone(),
+            in_root: false,
+            done: false,
+            record_type: PhantomData,
+        }))
+    }
+
+    fn writer_to(
+        &self,
+        sink: Box<dyn Write>,
+        name: &str,
+    ) -> Result<Box<dyn RecordWriter<T>>, DocumentError> {
+        for element in [&self.root, &self.row] {
+            if !is_xml_name(element) {
+                return Err(DocumentError::schema_mismatch(
+                    name,
+                    Location::default(),
+                    format!("`{}` is not a valid xml element name", element),
+                ));
+            }
+        }
+        let io_error = |err| DocumentError::io(name, err);
+        let mut out = BufWriter::new(sink);
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#).map_err(io_error)?;
+        writeln!(out, "<{}>", self.root).map_err(io_error)?;
+        Ok(Box::new(XmlRecordWriter {
+            file_name: name.to_string(),
+            out,
+            root: self.root.clone(),
+            row: self.row.clone(),
+        }))
+    }
+}
+
+/// Keeps track of the current line while quick_xml consumes the input,
+/// so errors can point at a line without holding on to the document.
+struct LineCounter<R> {
+    inner: R,
+    line: usize,
+}
+
+impl<R: BufRead> LineCounter<R> {
+    fn new(inner: R) -> Self {
+        LineCounter { inner, line: 1 }
+    }
+}
+
+impl<R: BufRead> Read for LineCounter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.line += buf[..read].iter().filter(|&&b| b == b'\n').count();
+        Ok(read)
+    }
+}
+
+impl<R: BufRead> BufRead for LineCounter<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if let Ok(buf) = self.inner.fill_buf() {
+            self.line += buf[..amt.min(buf.len())]
+                .iter()
+                .filter(|&&b| b == b'\n')
+                .count();
+        }
+        self.inner.consume(amt);
+    }
+}
+
+type Fields = Vec<(String, String)>;
+
+/// The fields of one row element, along with the line and offset where its start tag ends.
+struct Row {
+    fields: Fields,
+    line: usize,
+    offset: u64,
+}
+
+struct XmlRecords<'a, T> {
+    file_name: String,
+    reader: Reader<LineCounter<BufReader<Box<dyn Read + 'a>>>>,
+    buf: Vec<u8>,
+    root: String,
+    row: String,
+    in_root: bool,
+    done: bool,
+    record_type: PhantomData<T>,
+}
+
+impl<T: Record> XmlRecords<'_, T> {
+    /// An error pointing at the line and byte offset where the offending event ends.
+    fn error_at(&self, line: usize, offset: u64, message: String) -> DocumentError {
+        DocumentError::parse(&self.file_name, xml_location(line, offset), message)
+    }
+
+    /// Turns a quick_xml failure into an error at the position where reading stopped.
+    fn read_error(&self, line: usize, err: quick_xml::Error) -> DocumentError {
+        match err {
+            quick_xml::Error::Io(err) => {
+                DocumentError::io(&self.file_name, io::Error::new(err.kind(), err.to_string()))
+            }
+            err => {
+                let offset = self.reader.error_position();
+                self.error_at(line, offset, format!("malformed xml, {}", err))
+            }
+        }
+    }
+
+    /// Reads up to the next row element, or returns `None` once the root is closed.
+    fn next_row(&mut self) -> Result<Option<Row>, DocumentError> {
+        loop {
+            let event = self.reader.read_event_into(&mut self.buf);
+            let line = self.reader.get_ref().line;
+            let offset = self.reader.buffer_position();
+            let event = match event {
+                Ok(event) => event,
+                Err(err) => return Err(self.read_error(line, err)),
+            };
+            let (start, is_empty) = match event {
+                Event::Start(start) => (start.into_owned(), false),
+                Event::Empty(start) => (start.into_owned(), true),
+                Event::End(_) => return Ok(None),
+                Event::Eof => {
+                    let message = format!("expected a <{}> element", self.root);
+                    return Err(self.error_at(line, offset, message));
+                }
+                _ => continue,
+            };
+            let name = String::from_utf8_lossy(start.name().as_ref()).into_owned();
+
+            if !self.in_root {
+                if name != self.root {
+                    let message = format!("expected <{}> as the root, found <{}>", self.root, name);
+                    return Err(self.error_at(line, offset, message));
+                }
+                if is_empty {
+                    return Ok(None);
+                }
+                self.in_root = true;
+                continue;
+            }
+            if name != self.row {
+                let message = format!("expected a <{}> row, found <{}>", self.row, name);
+                return Err(self.error_at(line, offset, message));
+            }
+            let mut fields = self.attributes(&start, line, offset)?;
+            if !is_empty {
+                self.read_child_fields(&mut fields)?;
+            }
+            return Ok(Some(Row {
+                fields,
+                line,
+                offset,
+            }));
+        }
+    }
+
+    fn attributes(
+        &self,
+        start: &BytesStart,
+        line: usize,
+        offset: u64,
+    ) -> Result<Fields, DocumentError> {
+        let mut fields = Vec::new();
+        for attribute in start.attributes() {
+            let attribute = attribute.map_err(|err| {
+                self.error_at(line, offset, format!("malformed attribute, {}", err))
+            })?;
+            let value = attribute
+                .decode_and_unescape_value(self.reader.decoder())
+                .map_err(|err| self.error_at(line, offset, err.to_string()))?;
+            let key = String::from_utf8_lossy(attribute.key.as_ref()).into_owned();
+            fields.push((key, value.into_owned()));
+        }
+        Ok(fields)
+    }
+
+    /// Collects `<field>text</field>` children until the row element is closed. A field's
+    /// text comes in pieces around entities like `&amp;`, so it is only trimmed once whole.
+    fn read_child_fields(&mut self, fields: &mut Fields) -> Result<(), DocumentError> {
+        let mut field: Option<(String, String)> = None;
+        loop {
+            let event = self.reader.read_event_into(&mut self.buf);
+            let line = self.reader.get_ref().line;
+            let offset = self.reader.buffer_position();
+            let event = match event {
+                Ok(event) => event.into_owned(),
+                Err(err) => return Err(self.read_error(line, err)),
+            };
+            let text = match (&event, &mut field) {
+                (Event::Start(start), None) => {
+                    let name = String::from_utf8_lossy(start.name().as_ref()).into_owned();
+                    field = Some((name, String::new()));
+                    continue;
+                }
+                (Event::Start(start), Some((parent, _))) => {
+                    let message = format!(
+                        "<{}> is nested inside the field <{}>, only flat records are supported",
+                        String::from_utf8_lossy(start.name().as_ref()),
+                        parent
+                    );
+                    return Err(self.error_at(line, offset, message));
+                }
+                (Event::Empty(start), None) => {
+                    let name = String::from_utf8_lossy(start.name().as_ref()).into_owned();
+                    fields.push((name, String::new()));
+                    continue;
+                }
+                (Event::End(_), Some(_)) => {
+                    fields.extend(
+                        field
+                            .take()
+                            .map(|(name, value)| (name, value.trim().to_string())),
+                    );
+                    continue;
+                }
+                (Event::End(_), None) => return Ok(()),
+                (Event::Eof, _) => {
+                    let message = format!("the <{}> row is never closed", self.row);
+                    return Err(self.error_at(line, offset, message));
+                }
+                (Event::Text(text), Some(_)) => text.xml_content().map(|text| text.into_owned()),
+                (Event::CData(data), Some(_)) => data.decode().map(|text| text.into_owned()),
+                (Event::GeneralRef(reference), Some(_)) => match reference.resolve_char_ref() {
+                    Ok(Some(ch)) => Ok(ch.to_string()),
+                    _ => {
+                        let name = reference.decode().unwrap_or_default();
+                        match resolve_predefined_entity(&name) {
+                            Some(resolved) => Ok(resolved.to_string()),
+                            None => {
+                                let message = format!("unknown entity &{};", name);
+                                return Err(self.error_at(line, offset, message));
+                            }
+                        }
+                    }
+                },
+                // Text sitting directly in the row, comments and the like carry no field.
+                _ => continue,
+            };
+            let text = text.map_err(|err| self.error_at(line, offset, err.to_string()))?;
+            if let Some((_, value)) = &mut field {
+                value.push_str(&text);
+            }
+        }
+    }
+}
+
+impl<T: Record> Iterator for XmlRecords<'_, T> {
+    type Item = Result<T, DocumentError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.next_row() {
+            Ok(Some(row)) => {
+                let fields = row
+                    .fields
+                    .into_iter()
+                    .map(|(key, value)| (key.clone(), TextValue::new(key, value)));
+                Some(T::deserialize(MapDeserializer::new(fields)).map_err(|err| {
+                    DocumentError::schema_mismatch(
+                        &self.file_name,
+                        xml_location(row.line, row.offset),
+                        format!("<{}> does not fit the record, {}", self.row, err),
+                    )
+                }))
+            }
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// quick_xml knows byte offsets but not columns.
+fn xml_location(line: usize, offset: u64) -> Location {
+    Location {
+        line: Some(line),
+        column: None,
+        offset: Some(offset),
+    }
+}
+
+/// Writes every record as an empty row element with one attribute per field.
+struct XmlRecordWriter {
+    file_name: String,
+    out: BufWriter<Box<dyn Write>>,
+    root: String,
+    row: String,
+}
+
+impl<T: Record> RecordWriter<T> for XmlRecordWriter {
+    fn write_record(&mut self, record: &T) -> Result<(), DocumentError> {
+        let mismatch = |message: String| {
+            DocumentError::schema_mismatch(&self.file_name, Location::default(), message)
+        };
+        let value = serde_json::to_value(record).map_err(|err| mismatch(err.to_string()))?;
+        let serde_json::Value::Object(fields) = value else {
+            return Err(mismatch(
+                "an xml row can only be written from a record with named fields".to_string(),
+            ));
+        };
+        let mut element = format!("  <{}", self.row);
+        for (key, value) in fields {
+            if !is_xml_name(&key) {
+                return Err(mismatch(format!(
+                    "the field `{}` is not a valid xml attribute name",
+                    key
+                )));
+            }
+            let text = match value {
+                serde_json::Value::Null => continue,
+                serde_json::Value::String(text) => text,
+                serde_json::Value::Bool(_) | serde_json::Value::Number(_) => value.to_string(),
+                serde_json::Value::Array(_) | serde_json::Value::Object(_) => {
+                    return Err(mismatch(format!(
+                        "the field `{}` is nested, an xml attribute cannot hold it",
+                        key
+                    )));
+                }
+            };
+            element.push_str(&format!(" {}=\"{}\"", key, escape(text.as_str())));
+        }
+        element.push_str("/>\n");
+        self.out
+            .write_all(element.as_bytes())
+            .map_err(|err| DocumentError::io(&self.file_name, err))
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), DocumentError> {
+        let file_name = self.file_name;
+        let io_error = |err| DocumentError::io(&file_name, err);
+        writeln!(self.out, "</{}>", self.root).map_err(io_error)?;
+        self.out.flush().map_err(io_error)
+    }
+}
+
+/// Whether `name` is an xml name, as in the `Name` production of the xml spec. Colons
+/// are left out, since they would need a namespace declared for their prefix.
+fn is_xml_name(name: &str) -> bool {
+    let is_start = |c: char| {
+        matches!(c,
+            'A'..='Z' | '_' | 'a'..='z' | '\u{C0}'..='\u{D6}' | '\u{D8}'..='\u{F6}'
+            | '\u{F8}'..='\u{2FF}' | '\u{370}'..='\u{37D}' | '\u{37F}'..='\u{1FFF}'
+            | '\u{200C}'..='\u{200D}' | '\u{2070}'..='\u{218F}' | '\u{2C00}'..='\u{2FEF}'
+            | '\u{3001}'..='\u{D7FF}' | '\u{F900}'..='\u{FDCF}' | '\u{FDF0}'..='\u{FFFD}'
+            | '\u{10000}'..='\u{EFFFF}')
+    };
+    let is_char = |c: char| {
+        is_start(c)
+            || matches!(c,
+                '-' | '.' | '0'..='9' | '\u{B7}' | '\u{300}'..='\u{36F}' | '\u{203F}'..='\u{2040}')
+    };
+    let mut chars = name.chars();
+    chars.next().is_some_and(is_start) && chars.all(is_char)
+}
@@ -0,0 +1,170 @@
+use super::{
+    DocumentError, DocumentProcessor, Location, Record, RecordIter, RecordWriter,
+    error::{is_schema_message, strip_position},
+};
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use std::{
+    fmt,
+    io::{BufWriter, Read, Write},
+    marker::PhantomData,
+};
+
+pub struct YamlProcessor {}
+
+impl<T: Record> DocumentProcessor<T> for YamlProcessor {
+    /// Every document of a `---` separated stream is read in turn. A document holding a
+    /// sequence yields one record per element, any other document is a single record.
+    /// serde_yaml parses the whole stream up front, so unlike csv and json this is not constant memory.
+    fn read_from<'a>(
+        &self,
+        source: Box<dyn Read + 'a>,
+        name: &str,
+    ) -> Result<RecordIter<'a, T>, DocumentError> {
+        Ok(Box::new(YamlRecords {
+            file_name: name.to_string(),
+            documents: serde_yaml::Deserializer::from_reader(source),
+            pending: Vec::new().into_iter(),
+            last_error: None,
+            done: false,
+        }))
+    }
+
+    fn writer_to(
+        &self,
+        sink: Box<dyn Write>,
+        name: &str,
+    ) -> Result<Box<dyn RecordWriter<T>>, DocumentError> {
+        Ok(Box::new(YamlRecordWriter {
+            file_name: name.to_string(),
+            out: BufWriter::new(sink),
+            count: 0,
+        }))
+    }
+}
+
+/// serde_yaml has one error type for everything, so a record that does not fit the
+/// record type is told apart from broken yaml by serde's wording.
+fn yaml_error(path: &str, err: serde_yaml::Error) -> DocumentError {
+    let location = err
+        .location()
+        .map_or_else(Location::default, |at| Location {
+            line: Some(at.line()),
+            column: Some(at.column()),
+            offset: Some(at.index() as u64),
+        });
+    let message = err.to_string();
+    let message = strip_position(&message);
+    if is_schema_message(message) {
+        DocumentError::schema_mismatch(path, location, message)
+    } else {
+        DocumentError::parse(path, location, message)
+    }
+}
+
+struct YamlRecords<'a, T> {
+    file_name: String,
+    documents: serde_yaml::Deserializer<'a>,
+    pending: std::vec::IntoIter<T>,
+    last_error: Option<Option<(usize, usize)>>,
+    done: bool,
+}
+
+impl<T: Record> Iterator for YamlRecords<'_, T> {
+    type Item = Result<T, DocumentError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(record) = self.pending.next() {
+                return Some(Ok(record));
+            }
+            if self.done {
+                return None;
+            }
+            let document = self.documents.next()?;
+            match DocumentRecords(PhantomData).deserialize(document) {
+                Ok(records) => {
+                    self.last_error = None;
+                    self.pending = records.into_iter();
+                }
+                Err(err) => {
+                    // After a syntax error libyaml keeps handing out the same error for every
+                    // following document, so a repeat means the rest of the stream is unreadable.
+                    let position = err.location().map(|at| (at.line(), at.column()));
+                    if self.last_error == Some(position) {
+                        self.done = true;
+                        return None;
+                    }
+                    self.last_error = Some(position);
+                    return Some(Err(yaml_error(&self.file_name, err)));
+                }
+            }
+        }
+    }
+}
+
+/// Deserializes one yaml document into the records it holds, without going through
+/// `serde_yaml::Value`, so errors inside the document keep their position.
+struct DocumentRecords<T>(PhantomData<T>);
+
+impl<'de, T: Record> DeserializeSeed<'de> for DocumentRecords<T> {
+    type Value = Vec<T>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Vec<T>, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, T: Record> Visitor<'de> for DocumentRecords<T> {
+    type Value = Vec<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a record or a sequence of records")
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Vec<T>, E> {
+        // An empty document, e.g. a trailing `---`.
+        Ok(Vec::new())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<T>, A::Error> {
+        let mut records = Vec::new();
+        while let Some(record) = seq.next_element()? {
+            records.push(record);
+        }
+        Ok(records)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Vec<T>, A::Error> {
+        let record = T::deserialize(de::value::MapAccessDeserializer::new(map))?;
+        Ok(vec![record])
+    }
+}
+
+/// Writes a lone record as a plain document and several records as a `---` separated stream.
+struct YamlRecordWriter {
+    file_name: String,
+    out: BufWriter<Box<dyn Write>>,
+    count: usize,
+}
+
+impl<T: Record> RecordWriter<T> for YamlRecordWriter {
+    fn write_record(&mut self, record: &T) -> Result<(), DocumentError> {
+        let document = serde_yaml::to_string(record).map_err(|err| {
+            DocumentError::schema_mismatch(&self.file_name, Location::default(), err.to_string())
+        })?;
+        let separator: &[u8] = if self.count > 0 { b"---\n" } else { b"" };
+        self.out
+            .write_all(separator)
+            .and_then(|()| self.out.write_all(document.as_bytes()))
+            .map_err(|err| DocumentError::io(&self.file_name, err))?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), DocumentError> {
+        let file_name = self.file_name;
+        self.out
+            .flush()
+            .map_err(|err| DocumentError::io(&file_name, err))
+    }
+}
@@ -1,6 +1,284 @@
-pub mod creational;
+use lld_rust::creational::{self, factory_method};
+use std::{env, process};
+
+const USAGE: &str = "usage: lld-rust convert <input> <output> [--from <format>] [--to <format>]
+       lld-rust batch <directory|glob> [--threads <n>]
+       lld-rust diff <left> <right> [--key <field>]... [--json]
+       lld-rust query <file> <expression>
+       lld-rust aggregate <file> <aggregate>... [--by <field>] [--prefix <n>] [--where <expression>]
+
+An aggregate is `count`, or one of sum, min, max, mean and distinct with a field, like `mean:age`.
+
+Formats default to the file extensions; the input format is sniffed from the
+content when the extension is unknown. Exits with 2 when some records could not be converted,
+some files of a batch could not be read, or the documents of a diff differ.";
+
+const DEFAULT_THREADS: usize = 4;
 
 fn main() {
-    // crate::creational::factory_method::run();
-    crate::creational::abstract_factory::run();
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        None => {
+            // creational::factory_method::run();
+            creational::abstract_factory::run();
+        }
+        Some("convert") => process::exit(convert(&args[1..])),
+        Some("batch") => process::exit(batch(&args[1..])),
+        Some("diff") => process::exit(diff(&args[1..])),
+        Some("query") => process::exit(query(&args[1..])),
+        Some("aggregate") => process::exit(aggregate(&args[1..])),
+        Some(_) => {
+            eprintln!("{}", USAGE);
+            process::exit(1);
+        }
+    }
+}
+
+fn convert(args: &[String]) -> i32 {
+    let mut files = Vec::new();
+    let mut from = None;
+    let mut to = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--from" => from = args.next(),
+            "--to" => to = args.next(),
+            _ => files.push(arg),
+        }
+    }
+    let [input, output] = files[..] else {
+        eprintln!("{}", USAGE);
+        return 1;
+    };
+
+    let registry = factory_method::ProcessorRegistry::default();
+    match factory_method::convert(
+        &registry,
+        input,
+        from.map(String::as_str),
+        output,
+        to.map(String::as_str),
+    ) {
+        Ok(report) => {
+            for failure in &report.failures {
+                eprintln!("record {}: {}", failure.index, failure.error);
+            }
+            println!(
+                "converted {} records from {} to {}, {} failed",
+                report.written,
+                input,
+                output,
+                report.failures.len()
+            );
+            if report.failures.is_empty() { 0 } else { 2 }
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            1
+        }
+    }
+}
+
+fn batch(args: &[String]) -> i32 {
+    let mut patterns = Vec::new();
+    let mut threads = DEFAULT_THREADS;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--threads" => match args.next().map(|n| n.parse()) {
+                Some(Ok(n)) => threads = n,
+                _ => {
+                    eprintln!("{}", USAGE);
+                    return 1;
+                }
+            },
+            _ => patterns.push(arg),
+        }
+    }
+    let [pattern] = patterns[..] else {
+        eprintln!("{}", USAGE);
+        return 1;
+    };
+
+    let registry = factory_method::ProcessorRegistry::<factory_method::DynamicRecord>::default();
+    match factory_method::process_batch(&registry, pattern, threads) {
+        Ok(report) => {
+            for file in &report.files {
+                match &file.result {
+                    Ok(records) => println!(
+                        "{}: {} records in {:.2?}",
+                        file.path,
+                        records.len(),
+                        file.elapsed
+                    ),
+                    Err(err) => eprintln!("{}", err),
+                }
+            }
+            println!("{}", report.summary);
+            if report.summary.failed == 0 { 0 } else { 2 }
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            1
+        }
+    }
+}
+
+fn diff(args: &[String]) -> i32 {
+    let mut files = Vec::new();
+    let mut keys = Vec::new();
+    let mut json = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--key" => match args.next() {
+                Some(key) => keys.push(key.as_str()),
+                None => {
+                    eprintln!("{}", USAGE);
+                    return 1;
+                }
+            },
+            "--json" => json = true,
+            _ => files.push(arg),
+        }
+    }
+    let [left, right] = files[..] else {
+        eprintln!("{}", USAGE);
+        return 1;
+    };
+
+    let registry = factory_method::ProcessorRegistry::<factory_method::DynamicRecord>::default();
+    let result =
+        factory_method::DocumentEditorFactory::create_editor_auto_with(&registry, left.clone())
+            .and_then(|left| {
+                let right = factory_method::DocumentEditorFactory::create_editor_auto_with(
+                    &registry,
+                    right.clone(),
+                )?;
+                factory_method::diff(&left, &right, &keys)
+            });
+    match result {
+        Ok(diff) => {
+            if json {
+                println!("{}", diff.to_json());
+            } else {
+                println!("{}", diff);
+            }
+            if diff.is_empty() { 0 } else { 2 }
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            1
+        }
+    }
+}
+
+/// Prints the records that pass the filter as json lines.
+fn query(args: &[String]) -> i32 {
+    let [file, expression] = args else {
+        eprintln!("{}", USAGE);
+        return 1;
+    };
+
+    let registry = factory_method::ProcessorRegistry::<factory_method::DynamicRecord>::default();
+    let records = factory_method::Filter::parse(expression).and_then(|filter| {
+        factory_method::DocumentEditorFactory::create_editor_auto_with(&registry, file.clone())?
+            .filter(&filter)
+    });
+    let result = records.and_then(|records| {
+        for record in records {
+            let record = record?;
+            println!(
+                "{}",
+                serde_json::to_string(&record).expect("records are json")
+            );
+        }
+        Ok(())
+    });
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("{}", err);
+            1
+        }
+    }
+}
+
+fn aggregate(args: &[String]) -> i32 {
+    let mut file = None;
+    let mut aggregates = Vec::new();
+    let mut by = None;
+    let mut prefix = None;
+    let mut filter = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let parsed = match arg.as_str() {
+            "--by" => args.next().map(|field| by = Some(field)),
+            "--prefix" => args
+                .next()
+                .and_then(|len| len.parse().ok())
+                .map(|len| prefix = Some(len)),
+            "--where" => args.next().map(|expression| filter = Some(expression)),
+            _ if file.is_none() => {
+                file = Some(arg);
+                Some(())
+            }
+            aggregate => parse_aggregate(aggregate).map(|aggregate| aggregates.push(aggregate)),
+        };
+        if parsed.is_none() {
+            eprintln!("{}", USAGE);
+            return 1;
+        }
+    }
+    let Some(file) = file else {
+        eprintln!("{}", USAGE);
+        return 1;
+    };
+    let aggregation = aggregates.into_iter().fold(
+        factory_method::Aggregation::new(),
+        |aggregation, aggregate| aggregation.aggregate(aggregate),
+    );
+    let aggregation = match (by, prefix) {
+        (Some(field), Some(len)) => aggregation.group_by_prefix(field, len),
+        (Some(field), None) => aggregation.group_by(field),
+        (None, _) => aggregation,
+    };
+
+    let registry = factory_method::ProcessorRegistry::<factory_method::DynamicRecord>::default();
+    let result =
+        factory_method::DocumentEditorFactory::create_editor_auto_with(&registry, file.clone())
+            .and_then(|editor| match filter {
+                Some(expression) => {
+                    let filter = factory_method::Filter::parse(expression)?;
+                    aggregation.run(editor.filter(&filter)?, file)
+                }
+                None => editor.aggregate(&aggregation),
+            });
+    match result {
+        Ok(report) => {
+            println!("{}", report);
+            0
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            1
+        }
+    }
+}
+
+fn parse_aggregate(aggregate: &str) -> Option<factory_method::Aggregate> {
+    use factory_method::Aggregate;
+    if aggregate == "count" {
+        return Some(Aggregate::Count);
+    }
+    let (name, field) = aggregate.split_once(':')?;
+    let field = field.to_string();
+    match name {
+        "sum" => Some(Aggregate::Sum(field)),
+        "min" => Some(Aggregate::Min(field)),
+        "max" => Some(Aggregate::Max(field)),
+        "mean" => Some(Aggregate::Mean(field)),
+        "distinct" => Some(Aggregate::Distinct(field)),
+        _ => None,
+    }
 }